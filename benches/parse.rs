@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+use resp_parser::borrowed::from_bytes_borrowed;
+use resp_parser::deserializer::{from_bytes, from_stream, Value};
+use resp_parser::serializer::to_bytes;
+
+fn encode(value: &Value) -> Vec<u8> {
+    to_bytes(value).unwrap()
+}
+
+fn large_bulk_string(c: &mut Criterion) {
+    let data = encode(&Value::BulkString(vec![b'x'; 1024 * 1024]));
+    let mut group = c.benchmark_group("large_bulk_string");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| from_bytes(black_box(&data)).unwrap())
+    });
+    group.bench_function("from_bytes_borrowed", |b| {
+        b.iter(|| from_bytes_borrowed(black_box(&data)).unwrap())
+    });
+    group.bench_function("from_stream", |b| {
+        b.iter(|| from_stream(black_box(&data[..])).unwrap())
+    });
+    group.finish();
+}
+
+fn nested_arrays(c: &mut Criterion) {
+    let mut value = Value::Integer(1);
+    for _ in 0..100 {
+        value = Value::Array(vec![value, Value::BulkString(b"sibling".to_vec())]);
+    }
+    let data = encode(&value);
+    let mut group = c.benchmark_group("nested_arrays");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| from_bytes(black_box(&data)).unwrap())
+    });
+    group.bench_function("from_bytes_borrowed", |b| {
+        b.iter(|| from_bytes_borrowed(black_box(&data)).unwrap())
+    });
+    group.bench_function("from_stream", |b| {
+        b.iter(|| from_stream(black_box(&data[..])).unwrap())
+    });
+    group.finish();
+}
+
+fn small_integers(c: &mut Criterion) {
+    let data = encode(&Value::Array((0..10_000).map(Value::Integer).collect()));
+    let mut group = c.benchmark_group("small_integers");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| from_bytes(black_box(&data)).unwrap())
+    });
+    group.bench_function("from_bytes_borrowed", |b| {
+        b.iter(|| from_bytes_borrowed(black_box(&data)).unwrap())
+    });
+    group.bench_function("from_stream", |b| {
+        b.iter(|| from_stream(black_box(&data[..])).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, large_bulk_string, nested_arrays, small_integers);
+criterion_main!(benches);
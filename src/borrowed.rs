@@ -0,0 +1,453 @@
+use std::str;
+
+use crate::deserializer::{
+    body_length_mismatch, check_big_number, double_from_str, integer_from_str,
+    lone_carriage_return, split_verbatim, too_many_digits, Error, Result, MAX_LENGTH_DIGITS,
+    MAX_PREALLOCATED_ELEMENTS,
+};
+use crate::value::{NullKind, Value};
+
+/// Zero-copy counterpart of [`Value`](crate::deserializer::Value): strings and
+/// bulk bodies borrow from the buffer they were parsed from.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ValueRef<'a> {
+    String(&'a str),
+    Error(&'a str),
+    Integer(i64),
+    Double(f64),
+    BigNumber(&'a str),
+    BulkString(&'a [u8]),
+    BulkError(&'a [u8]),
+    Verbatim {
+        format: &'a str,
+        data: &'a [u8],
+    },
+    Array(Vec<ValueRef<'a>>),
+    Set(Vec<ValueRef<'a>>),
+    Null(NullKind),
+    Map(Vec<(ValueRef<'a>, ValueRef<'a>)>),
+    WithAttributes {
+        attributes: Box<ValueRef<'a>>,
+        value: Box<ValueRef<'a>>,
+    },
+}
+
+impl<'a> ValueRef<'a> {
+    /// Copies the borrowed data into an owned [`Value`].
+    pub fn to_owned(&self) -> Value {
+        match self {
+            ValueRef::String(s) => Value::String(s.to_string()),
+            ValueRef::Error(e) => Value::Error(e.to_string()),
+            ValueRef::Integer(i) => Value::Integer(*i),
+            ValueRef::Double(d) => Value::Double(*d),
+            ValueRef::BigNumber(n) => Value::BigNumber(n.to_string()),
+            ValueRef::BulkString(b) => Value::BulkString(b.to_vec()),
+            ValueRef::BulkError(b) => Value::BulkError(b.to_vec()),
+            ValueRef::Verbatim { format, data } => Value::Verbatim {
+                format: format.to_string(),
+                data: data.to_vec(),
+            },
+            ValueRef::Array(items) => Value::Array(items.iter().map(ValueRef::to_owned).collect()),
+            ValueRef::Set(items) => Value::Set(items.iter().map(ValueRef::to_owned).collect()),
+            ValueRef::Null(kind) => Value::Null(*kind),
+            ValueRef::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect(),
+            ),
+            ValueRef::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(ValueRef::to_owned(attributes)),
+                value: Box::new(ValueRef::to_owned(value)),
+            },
+        }
+    }
+
+    /// Consumes the reference, copying the borrowed data into an owned [`Value`].
+    pub fn into_owned(self) -> Value {
+        self.to_owned()
+    }
+}
+
+#[derive(Debug)]
+struct SliceParser<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SliceParser<'a> {
+    fn new(data: &'a [u8]) -> SliceParser<'a> {
+        SliceParser { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    fn next_byte(&mut self) -> Result<u8> {
+        let c = *self.data.get(self.position).ok_or(Error::EndOfStream)?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn check_ending(&mut self) -> Result<()> {
+        match self.next_byte()? {
+            b'\n' => Ok(()),
+            c => Err(lone_carriage_return(c)),
+        }
+    }
+
+    fn read_line(&mut self) -> Result<&'a [u8]> {
+        let start = self.position;
+        loop {
+            match self.next_byte()? {
+                b'\r' => {
+                    self.check_ending()?;
+                    return Ok(&self.data[start..self.position - 2]);
+                }
+                b'\n' => {
+                    return Err(Error::InvalidValue("String contain \\n".to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<&'a str> {
+        let line = self.read_line()?;
+        str::from_utf8(line)
+            .map_err(|_| Error::InvalidValue("Non UTF-8 integer encoding".to_string()))
+    }
+
+    fn parse_integer(&mut self) -> Result<i64> {
+        integer_from_str(self.parse_string()?)
+    }
+
+    fn parse_double(&mut self) -> Result<f64> {
+        double_from_str(self.parse_string()?)
+    }
+
+    fn parse_big_number(&mut self) -> Result<&'a str> {
+        let line = self.parse_string()?;
+        check_big_number(line)?;
+        Ok(line)
+    }
+
+    fn parse_null(&mut self) -> Result<()> {
+        match self.next_byte()? {
+            b'\r' => self.check_ending(),
+            c => Err(Error::InvalidValue(format!(
+                "Expected CRLF after null, found `{}`",
+                c.escape_ascii()
+            ))),
+        }
+    }
+
+    fn parse_length(&mut self, context: &str, nullable: bool) -> Result<Option<usize>> {
+        let start = self.position;
+        loop {
+            match self.next_byte()? {
+                b'\r' => {
+                    self.check_ending()?;
+                    break;
+                }
+                _ if self.position - 1 - start == MAX_LENGTH_DIGITS => {
+                    return Err(too_many_digits(context));
+                }
+                c if c.is_ascii_digit() || (c == b'-' && self.position - 1 == start) => {}
+                c => {
+                    return Err(Error::InvalidValue(format!(
+                        "Expected CRLF after {} length, found `{}`",
+                        context,
+                        c.escape_ascii()
+                    )));
+                }
+            }
+        }
+        // Only ASCII digits and `-` got here, so this is valid UTF-8.
+        let line = str::from_utf8(&self.data[start..self.position - 2]).unwrap_or_default();
+        match line.parse::<i64>() {
+            Ok(-1) if nullable => Ok(None),
+            Ok(length) if length >= 0 => Ok(Some(length as usize)),
+            _ => Err(Error::InvalidValue(format!(
+                "Invalid {} length `{}`",
+                context, line
+            ))),
+        }
+    }
+
+    fn parse_bulk(&mut self) -> Result<Option<&'a [u8]>> {
+        // Chunks of a streamed string aren't contiguous, there's no single
+        // slice to borrow.
+        if self.data.get(self.position) == Some(&b'?') {
+            return Err(Error::InvalidValue(
+                "Streamed bulk strings can't be borrowed".to_string(),
+            ));
+        }
+        self.parse_blob("bulk", true)
+    }
+
+    fn parse_blob(&mut self, context: &str, nullable: bool) -> Result<Option<&'a [u8]>> {
+        let length = match self.parse_length(context, nullable)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        let start = self.position;
+        let end = start.saturating_add(length);
+        if end > self.data.len() {
+            return Err(Error::EndOfStream);
+        }
+        self.position = end;
+        let ending = [self.next_byte()?, self.next_byte()?];
+        if &ending != b"\r\n" {
+            return Err(body_length_mismatch(context, length, &ending));
+        }
+        Ok(Some(&self.data[start..end]))
+    }
+
+    fn parse_array(&mut self) -> Result<Option<Vec<ValueRef<'a>>>> {
+        let length = match self.parse_length("array", true)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        let mut result = Vec::with_capacity(length.min(MAX_PREALLOCATED_ELEMENTS));
+        for i in 0..length {
+            if matches!(self.data.get(self.position), Some(b'\r' | b'\n')) {
+                return Err(Error::InvalidValue(format!(
+                    "Expected element {} of array, found line terminator",
+                    i + 1
+                )));
+            }
+            result.push(self.parse()?);
+        }
+        Ok(Some(result))
+    }
+
+    fn parse_elements(&mut self, context: &str) -> Result<Vec<ValueRef<'a>>> {
+        let length = self.parse_length(context, false)?.unwrap_or_default();
+        let mut result = Vec::with_capacity(length.min(MAX_PREALLOCATED_ELEMENTS));
+        for _ in 0..length {
+            result.push(self.parse()?);
+        }
+        Ok(result)
+    }
+
+    fn parse_map(&mut self) -> Result<Vec<(ValueRef<'a>, ValueRef<'a>)>> {
+        let length = self.parse_length("map", false)?.unwrap_or_default();
+        let mut result = Vec::with_capacity(length.min(MAX_PREALLOCATED_ELEMENTS));
+        for _ in 0..length {
+            let key = self.parse()?;
+            let value = self.parse()?;
+            result.push((key, value));
+        }
+        Ok(result)
+    }
+
+    fn parse_attributes(&mut self) -> Result<ValueRef<'a>> {
+        let attributes = ValueRef::Map(self.parse_map()?);
+        let value = self.parse()?;
+        Ok(ValueRef::WithAttributes {
+            attributes: Box::new(attributes),
+            value: Box::new(value),
+        })
+    }
+
+    fn parse(&mut self) -> Result<ValueRef<'a>> {
+        match self.next_byte()? {
+            b'+' => Ok(ValueRef::String(self.parse_string()?)),
+            b'-' => Ok(ValueRef::Error(self.parse_string()?)),
+            b':' => Ok(ValueRef::Integer(self.parse_integer()?)),
+            b'$' => Ok(self
+                .parse_bulk()?
+                .map_or(ValueRef::Null(NullKind::BulkString), ValueRef::BulkString)),
+            b'*' => Ok(self
+                .parse_array()?
+                .map_or(ValueRef::Null(NullKind::Array), ValueRef::Array)),
+            b',' => Ok(ValueRef::Double(self.parse_double()?)),
+            b'(' => Ok(ValueRef::BigNumber(self.parse_big_number()?)),
+            b'=' => {
+                let body = self
+                    .parse_blob("verbatim string", false)?
+                    .unwrap_or_default();
+                let (format, data) = split_verbatim(body)?;
+                Ok(ValueRef::Verbatim { format, data })
+            }
+            b'!' => Ok(ValueRef::BulkError(
+                self.parse_blob("bulk error", false)?.unwrap_or_default(),
+            )),
+            b'>' => Ok(ValueRef::Array(self.parse_elements("push")?)),
+            b'~' => Ok(ValueRef::Set(self.parse_elements("set")?)),
+            b'%' => Ok(ValueRef::Map(self.parse_map()?)),
+            b'|' => self.parse_attributes(),
+            b'_' => {
+                self.parse_null()?;
+                Ok(ValueRef::Null(NullKind::Resp3))
+            }
+            b'\r' | b'\n' => Err(Error::InvalidValue(
+                "Expected a type prefix, found line terminator".to_string(),
+            )),
+            c => Err(Error::InvalidValue(format!("Invalid character {}", c))),
+        }
+    }
+}
+
+/// Parses a single value borrowing from `data`.
+pub fn from_bytes_borrowed(data: &[u8]) -> Result<ValueRef<'_>> {
+    SliceParser::new(data).parse()
+}
+
+// Parses the first value in `data`, also returning how many bytes it took.
+pub(crate) fn parse_prefix(data: &[u8]) -> Result<(ValueRef<'_>, usize)> {
+    let mut parser = SliceParser::new(data);
+    let value = parser.parse()?;
+    Ok((value, parser.position))
+}
+
+/// Parses every value in a pipeline buffer, borrowing from `data`.
+///
+/// Fails if the buffer ends in the middle of a frame.
+pub fn parse_all_borrowed(data: &[u8]) -> Result<Vec<ValueRef<'_>>> {
+    let mut parser = SliceParser::new(data);
+    let mut result = vec![];
+    while !parser.is_empty() {
+        result.push(parser.parse()?);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_array() {
+        let data = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n";
+        let result = from_bytes_borrowed(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = ValueRef::Array(vec![
+            ValueRef::BulkString(b"ECHO"),
+            ValueRef::BulkString(b"hey"),
+        ]);
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn into_owned_outlives_buffer() {
+        let value = {
+            let data = b"*3\r\n$4\r\nECHO\r\n+hey\r\n:1\r\n".to_vec();
+            from_bytes_borrowed(&data).unwrap().into_owned()
+        };
+        let correct = Value::Array(vec![
+            Value::BulkString(b"ECHO".to_vec()),
+            Value::String("hey".to_string()),
+            Value::Integer(1),
+        ]);
+        assert_eq!(value, correct);
+    }
+
+    #[test]
+    fn streamed_bulk_not_borrowed() {
+        let result = from_bytes_borrowed(b"$?\r\n;2\r\nhi\r\n;0\r\n");
+        assert!(
+            matches!(result, Err(Error::InvalidValue(_))),
+            "Streamed strings can't be borrowed. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_pipeline() {
+        let data = b"+OK\r\n$5\r\nhello\r\n*2\r\n:1\r\n$3\r\nhey\r\n";
+        let result = parse_all_borrowed(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = vec![
+            ValueRef::String("OK"),
+            ValueRef::BulkString(b"hello"),
+            ValueRef::Array(vec![ValueRef::Integer(1), ValueRef::BulkString(b"hey")]),
+        ];
+        assert_eq!(result, correct);
+
+        let buffer = data.as_ptr_range();
+        match (&result[1], &result[2]) {
+            (ValueRef::BulkString(hello), ValueRef::Array(items)) => {
+                assert_eq!(hello.as_ptr(), data[9..].as_ptr());
+                match items[1] {
+                    ValueRef::BulkString(hey) => assert!(buffer.contains(&hey.as_ptr())),
+                    ref v => panic!("Expected bulk string, found {:?}", v),
+                }
+            }
+            v => panic!("Unexpected values {:?}", v),
+        }
+    }
+
+    #[test]
+    fn parse_bulk_string_missing_length_ending() {
+        let result = from_bytes_borrowed(b"$4ECHO\r\n");
+        match result {
+            Err(Error::InvalidValue(msg)) => {
+                assert!(
+                    msg.starts_with("Expected CRLF after bulk length"),
+                    "{}",
+                    msg
+                )
+            }
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn parse_pipeline_truncated() {
+        let data = b"+OK\r\n$5\r\nhel";
+        let result = parse_all_borrowed(data);
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_stray_line_terminator() {
+        let result = from_bytes_borrowed(b"\r\n+OK\r\n");
+        match result {
+            Err(Error::InvalidValue(msg)) => {
+                assert_eq!(msg, "Expected a type prefix, found line terminator")
+            }
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn parse_empty_bulk_string() {
+        let result = parse_all_borrowed(b"$0\r\n\r\n:1\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let correct = vec![ValueRef::BulkString(b""), ValueRef::Integer(1)];
+        assert_eq!(result.unwrap(), correct);
+    }
+
+    #[test]
+    fn parse_invalid_lengths() {
+        for (data, message) in [
+            (&b"$-2\r\n"[..], "Invalid bulk length `-2`"),
+            (b"*-5\r\n", "Invalid array length `-5`"),
+            (b"%-1\r\n", "Invalid map length `-1`"),
+        ] {
+            match from_bytes_borrowed(data) {
+                Err(Error::InvalidValue(msg)) => assert_eq!(msg, message),
+                r => panic!("Expected invalid value error. Found: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_huge_declared_array() {
+        let result = from_bytes_borrowed(b"*2000000000\r\n:1\r\n");
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+    }
+}
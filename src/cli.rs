@@ -0,0 +1,251 @@
+use std::fmt;
+
+use crate::deserializer::Value;
+
+/// Renders `value` the way `redis-cli` prints replies in a terminal.
+pub fn to_cli_string(value: &Value) -> String {
+    value.to_string()
+}
+
+/// Renders a command, an array of bulk strings, as a line that can be pasted
+/// into `redis-cli`, quoting the arguments that need it. Any other value
+/// gives `None`.
+pub fn to_command_string(value: &Value) -> Option<String> {
+    let args = match value {
+        Value::Array(items) if !items.is_empty() => items,
+        _ => return None,
+    };
+    let mut result = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        let arg = match arg {
+            Value::BulkString(b) => b,
+            _ => return None,
+        };
+        if i > 0 {
+            result.push(' ');
+        }
+        let bare = !arg.is_empty()
+            && arg
+                .iter()
+                .all(|&c| c.is_ascii_graphic() && !matches!(c, b'"' | b'\'' | b'\\'));
+        if bare {
+            // Only ASCII got here.
+            result.push_str(&String::from_utf8_lossy(arg));
+        } else {
+            write_quoted(&mut result, arg).ok()?;
+        }
+    }
+    Some(result)
+}
+
+fn write_quoted<W: fmt::Write>(f: &mut W, bytes: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for &c in bytes {
+        match c {
+            b'\\' => write!(f, "\\\\")?,
+            b'"' => write!(f, "\\\"")?,
+            b'\n' => write!(f, "\\n")?,
+            b'\r' => write!(f, "\\r")?,
+            b'\t' => write!(f, "\\t")?,
+            0x07 => write!(f, "\\a")?,
+            0x08 => write!(f, "\\b")?,
+            c if c.is_ascii_graphic() || c == b' ' => write!(f, "{}", c as char)?,
+            c => write!(f, "\\x{:02x}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// Simple strings are printed bare, like `redis-cli` does, but control
+// characters are escaped so they can't mess with the terminal.
+fn write_escaped(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        if c.is_control() {
+            for b in c.to_string().bytes() {
+                write!(f, "\\x{:02x}", b)?;
+            }
+        } else {
+            write!(f, "{}", c)?;
+        }
+    }
+    Ok(())
+}
+
+fn index_width(length: usize) -> usize {
+    length.to_string().len()
+}
+
+fn write_cli(f: &mut fmt::Formatter, value: &Value, prefix: &str) -> fmt::Result {
+    match value {
+        Value::String(s) => write_escaped(f, s),
+        Value::Error(e) => {
+            write!(f, "(error) ")?;
+            write_escaped(f, e)
+        }
+        Value::BulkError(e) => {
+            write!(f, "(error) ")?;
+            write_escaped(f, &String::from_utf8_lossy(e))
+        }
+        Value::Integer(i) => write!(f, "(integer) {}", i),
+        Value::Double(d) => write!(f, "(double) {}", d),
+        Value::RawDouble { raw, .. } => write!(f, "(double) {}", raw),
+        Value::BigNumber(n) => write!(f, "(big number) {}", n),
+        Value::BulkString(b) => write_quoted(f, b),
+        // `redis-cli` prints the text as is, whatever the format, line
+        // breaks included.
+        Value::Verbatim { data, .. } => {
+            let text = String::from_utf8_lossy(data).replace("\r\n", "\n");
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write_escaped(f, line)?;
+            }
+            Ok(())
+        }
+        Value::Null(_) => write!(f, "(nil)"),
+        Value::Array(items) if items.is_empty() => write!(f, "(empty array)"),
+        Value::Set(items) if items.is_empty() => write!(f, "(empty set)"),
+        Value::Array(items) | Value::Set(items) => {
+            // Nested aggregates are indented past the `N) ` of their parent,
+            // the first element continues on the parent's line.
+            let width = index_width(items.len());
+            let nested = format!("{}{}", prefix, " ".repeat(width + 2));
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "\n{}", prefix)?;
+                }
+                write!(f, "{:>width$}) ", i + 1, width = width)?;
+                write_cli(f, item, &nested)?;
+            }
+            Ok(())
+        }
+        Value::Map(entries) if entries.is_empty() => write!(f, "(empty hash)"),
+        Value::Map(entries) => {
+            let width = index_width(entries.len());
+            let nested = format!("{}{}", prefix, " ".repeat(width + 2));
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "\n{}", prefix)?;
+                }
+                write!(f, "{:>width$}# ", i + 1, width = width)?;
+                write_cli(f, key, &nested)?;
+                write!(f, " => ")?;
+                write_cli(f, value, &nested)?;
+            }
+            Ok(())
+        }
+        Value::WithAttributes { value, .. } => write_cli(f, value, prefix),
+        Value::Unknown {
+            prefix: type_prefix,
+            raw_line,
+        } => {
+            write!(f, "(unknown {}) ", type_prefix.escape_ascii())?;
+            write_quoted(f, raw_line)
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_cli(f, self, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::NullKind;
+
+    #[test]
+    fn render_scalars() {
+        assert_eq!(to_cli_string(&Value::String("OK".to_string())), "OK");
+        assert_eq!(
+            to_cli_string(&Value::Error("ERR unknown command".to_string())),
+            "(error) ERR unknown command"
+        );
+        assert_eq!(to_cli_string(&Value::Integer(-3)), "(integer) -3");
+        assert_eq!(to_cli_string(&Value::Null(NullKind::BulkString)), "(nil)");
+        assert_eq!(
+            to_cli_string(&Value::BulkString(b"a \"b\"\r\n\x00".to_vec())),
+            "\"a \\\"b\\\"\\r\\n\\x00\""
+        );
+        assert_eq!(to_cli_string(&Value::Array(vec![])), "(empty array)");
+    }
+
+    #[test]
+    fn render_mixed_array() {
+        let value = Value::Array(vec![
+            Value::BulkString(b"foo".to_vec()),
+            Value::Integer(42),
+            Value::Array(vec![
+                Value::BulkString(b"a".to_vec()),
+                Value::Null(NullKind::BulkString),
+            ]),
+            Value::Error("ERR oops".to_string()),
+        ]);
+        let correct = "1) \"foo\"\n\
+                       2) (integer) 42\n\
+                       3) 1) \"a\"\n   \
+                          2) (nil)\n\
+                       4) (error) ERR oops";
+        assert_eq!(to_cli_string(&value), correct);
+    }
+
+    #[test]
+    fn render_wide_index() {
+        let value = Value::Array((0..10).map(Value::Integer).collect());
+        let result = to_cli_string(&value);
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[0], " 1) (integer) 0");
+        assert_eq!(lines[9], "10) (integer) 9");
+    }
+
+    #[test]
+    fn render_control_characters() {
+        let value = Value::String("a\x01b\x1b[2J".to_string());
+        assert_eq!(to_cli_string(&value), "a\\x01b\\x1b[2J");
+        let value = Value::Error("ERR \x07".to_string());
+        assert_eq!(to_cli_string(&value), "(error) ERR \\x07");
+        let value = Value::String("héllo".to_string());
+        assert_eq!(to_cli_string(&value), "héllo");
+    }
+
+    #[test]
+    fn render_command() {
+        let value = Value::Array(vec![
+            Value::BulkString(b"SET".to_vec()),
+            Value::BulkString(b"my key".to_vec()),
+            Value::BulkString(b"value".to_vec()),
+        ]);
+        assert_eq!(
+            to_command_string(&value),
+            Some("SET \"my key\" value".to_string())
+        );
+        let value = Value::Array(vec![
+            Value::BulkString(b"ECHO".to_vec()),
+            Value::BulkString(b"".to_vec()),
+            Value::BulkString(b"a\"b\n".to_vec()),
+        ]);
+        assert_eq!(
+            to_command_string(&value),
+            Some("ECHO \"\" \"a\\\"b\\n\"".to_string())
+        );
+        assert_eq!(to_command_string(&Value::Integer(1)), None);
+        assert_eq!(to_command_string(&Value::Array(vec![])), None);
+        let value = Value::Array(vec![
+            Value::BulkString(b"GET".to_vec()),
+            Value::Null(NullKind::Resp3),
+        ]);
+        assert_eq!(to_command_string(&value), None);
+    }
+
+    #[test]
+    fn render_verbatim() {
+        let value = Value::Verbatim {
+            format: "txt".to_string(),
+            data: b"# Server\r\nredis_version:7.2.0\x01".to_vec(),
+        };
+        assert_eq!(to_cli_string(&value), "# Server\nredis_version:7.2.0\\x01");
+    }
+}
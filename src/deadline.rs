@@ -0,0 +1,100 @@
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult};
+use std::time::{Duration, Instant};
+
+/// Reader adapter failing with `ErrorKind::TimedOut` once a deadline has
+/// passed, which the deserializer reports as `Error::Timeout`.
+///
+/// The deadline is checked before each read, so a read that blocks in the
+/// inner reader isn't interrupted.
+#[derive(Debug)]
+pub struct DeadlineReader<R> {
+    inner: R,
+    deadline: Instant,
+}
+
+impl<R: Read> DeadlineReader<R> {
+    pub fn new(inner: R, deadline: Instant) -> DeadlineReader<R> {
+        DeadlineReader { inner, deadline }
+    }
+
+    /// Sets the deadline `timeout` from now.
+    pub fn with_timeout(inner: R, timeout: Duration) -> DeadlineReader<R> {
+        DeadlineReader::new(inner, Instant::now() + timeout)
+    }
+
+    /// Moves the deadline, e.g. before reading the next request.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if Instant::now() >= self.deadline {
+            return Err(IoError::new(ErrorKind::TimedOut, "Read deadline exceeded"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deserializer::{Deserializer, Error, Value};
+    use std::thread;
+
+    // Hands out one byte per read, sleeping before each.
+    struct SlowReader {
+        data: Vec<u8>,
+        position: usize,
+        delay: Duration,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            thread::sleep(self.delay);
+            match self.data.get(self.position) {
+                Some(&c) if !buf.is_empty() => {
+                    buf[0] = c;
+                    self.position += 1;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn deadline_exceeded() {
+        let reader = SlowReader {
+            data: b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n".to_vec(),
+            position: 0,
+            delay: Duration::from_millis(5),
+        };
+        let reader = DeadlineReader::with_timeout(reader, Duration::from_millis(20));
+        let mut d = Deserializer::new(reader);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::Timeout(_))),
+            "Expected timeout. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn deadline_not_reached() {
+        let reader = DeadlineReader::with_timeout(&b":1\r\n"[..], Duration::from_secs(60));
+        let mut d = Deserializer::new(reader);
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Integer(1));
+    }
+}
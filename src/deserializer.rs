@@ -1,72 +1,474 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::io::Error as IoError;
+use std::io::ErrorKind;
 use std::io::Read;
+use std::io::Result as IoResult;
+use std::ops::ControlFlow;
 use std::result::Result as StdResult;
+use std::sync::Arc;
+
+use crate::borrowed::{from_bytes_borrowed, parse_prefix};
+pub use crate::frame::Frame;
+use crate::observer::Observer;
+pub use crate::observer::ParseObserver;
+use crate::serializer::ProtocolVersion;
+pub use crate::shared::{Interner, SharedValue};
+pub use crate::sink::ValueSink;
+pub use crate::value::{NullKind, Value, ValueType};
 
 pub type Result<T> = StdResult<T, Error>;
 
+/// Errors returned by the parser and serializer.
+///
+/// New kinds of failure may be added in minor releases, so matches outside
+/// this crate need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     IoError(IoError),
     InvalidValue(String),
     EndOfStream,
+    /// The input held no bytes at all, as opposed to ending in the middle
+    /// of a frame. Only returned by the one-shot functions like `from_bytes`.
+    EmptyInput,
+    /// A configured limit of the deserializer was hit.
+    LimitExceeded(String),
+    /// The stream's read timed out (`TimedOut` or `WouldBlock`). Bytes of the
+    /// interrupted frame are kept, so calling `parse` again resumes it.
+    Timeout(IoError),
+}
+
+impl Error {
+    pub(crate) fn from_io(error: IoError) -> Error {
+        match error.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => Error::Timeout(error),
+            _ => Error::IoError(error),
+        }
+    }
+}
+
+pub(crate) fn integer_from_str(s: &str) -> Result<i64> {
+    if s.is_empty() {
+        return Err(Error::InvalidValue("Empty integer".to_string()));
+    }
+    s.parse::<i64>().map_err(|_| {
+        if s.trim_start_matches(['+', '-']).eq_ignore_ascii_case("inf") {
+            Error::InvalidValue("Infinity is only valid for RESP3 doubles".to_string())
+        } else {
+            Error::InvalidValue(format!("Can't parse `{}` as integer", s))
+        }
+    })
+}
+
+// A blob body not followed by CRLF almost always means the declared length
+// doesn't match what the server actually sent.
+pub(crate) fn body_length_mismatch(context: &str, length: usize, found: &[u8]) -> Error {
+    let mut message = format!(
+        "{} body length mismatch: expected CRLF after {} bytes, found `{}`",
+        context,
+        length,
+        found.escape_ascii()
+    );
+    message[..1].make_ascii_uppercase();
+    Error::InvalidValue(message)
+}
+
+// Every line ends with CRLF, a `\r` followed by anything else is malformed.
+pub(crate) fn lone_carriage_return(found: u8) -> Error {
+    Error::InvalidValue(format!(
+        "Found `\\r` not followed by `\\n`, got `{}` instead",
+        found.escape_ascii()
+    ))
+}
+
+// Splits the body of a verbatim string into its format and text.
+pub(crate) fn split_verbatim(body: &[u8]) -> Result<(&str, &[u8])> {
+    if body.len() < 4 || body[3] != b':' {
+        return Err(Error::InvalidValue(
+            "Verbatim string must start with a 3 character format and `:`".to_string(),
+        ));
+    }
+    let format = std::str::from_utf8(&body[..3])
+        .map_err(|_| Error::InvalidValue("Non UTF-8 verbatim string format".to_string()))?;
+    check_verbatim_format(format)?;
+    Ok((format, &body[4..]))
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Value {
-    String(String),      // https://redis.io/docs/reference/protocol-spec/#simple-strings
-    Error(String),       // https://redis.io/docs/reference/protocol-spec/#simple-errors
-    Integer(i64),        // https://redis.io/docs/reference/protocol-spec/#integers
-    BulkString(Vec<u8>), // https://redis.io/docs/reference/protocol-spec/#bulk-strings
-    Array(Vec<Value>),   // https://redis.io/docs/reference/protocol-spec/#arrays
+// Shared with the serializer, so whatever parses can be written back.
+pub(crate) fn check_verbatim_format(format: &str) -> Result<()> {
+    if format.len() != 3 || !format.bytes().all(|c| c.is_ascii_alphabetic()) {
+        return Err(Error::InvalidValue(format!(
+            "Verbatim string format must be 3 ASCII letters, got `{}`",
+            format.escape_default()
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn double_from_str(s: &str) -> Result<f64> {
+    s.parse::<f64>()
+        .map_err(|_| Error::InvalidValue(format!("Can't parse `{}` as double", s)))
+}
+
+pub(crate) fn check_big_number(s: &str) -> Result<()> {
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(Error::InvalidValue(format!(
+            "Can't parse `{}` as big number",
+            s
+        )));
+    }
+    Ok(())
 }
+
 #[derive(Debug)]
-struct Deserialer<R: Read> {
+pub struct Deserializer<R: Read> {
     stream: R,
+    // Bytes read but not dropped yet and the position of the next one to
+    // parse. Consumed bytes are kept until the frame completes so it can be
+    // parsed again after a timeout, except by `parse_events` which drops them
+    // as it moves on.
+    pending: Vec<u8>,
+    replay: usize,
+    keep_frame: bool,
+    // Bytes consumed by the frame being parsed.
+    frame_bytes: usize,
+    // Whether the last consumed byte ended a line, for `recover_next`.
+    line_start: bool,
+    // Bytes consumed by finished frames, parsed or not, and skipped ahead.
+    consumed: u64,
+    unknown_types: bool,
+    strict_resp3: bool,
+    unique_map_keys: bool,
+    raw_doubles: bool,
+    max_length: usize,
+    max_elements: usize,
+    max_frame_bytes: usize,
+    bulk_capacity_hint: usize,
+    buffer_capacity: usize,
+    // Nesting level of the value being parsed.
+    depth: usize,
+    stats: Option<Stats>,
+    interner: Option<Interner>,
+    observer: Option<Observer>,
+    handlers: HashMap<u8, TypeHandler>,
+}
+
+// Parses the rest of the line of a frame with a registered type prefix.
+type HandlerFn = dyn FnMut(&[u8]) -> Result<Value> + Send;
+
+struct TypeHandler(Box<HandlerFn>);
+
+impl fmt::Debug for TypeHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("TypeHandler")
+    }
+}
+
+/// Counters collected by a `Deserializer` created `with_stats(true)`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Frames parsed successfully.
+    pub frames: u64,
+    /// Bytes consumed from the stream, including those of failed frames.
+    pub bytes: u64,
+    /// Deepest nesting seen, a scalar at the top level being 1.
+    pub max_depth: usize,
+    /// Length of the largest bulk string or bulk error body.
+    pub largest_bulk: usize,
+}
+
+/// Default for `Deserializer::with_max_length`, the same as Redis'
+/// `proto-max-bulk-len`.
+pub const DEFAULT_MAX_LENGTH: usize = 512 * 1024 * 1024;
+
+/// Default for `Deserializer::with_bulk_capacity_hint`.
+pub const DEFAULT_BULK_CAPACITY_HINT: usize = 16 * 1024;
+
+// Upper bound on what is reserved up front for an aggregate, whatever length
+// it declares; anything beyond grows as elements actually arrive.
+pub(crate) const MAX_PREALLOCATED_ELEMENTS: usize = 1024;
+
+// `-9223372036854775808` is 20 characters, no valid length needs more. Longer
+// runs are rejected before they're buffered.
+pub(crate) const MAX_LENGTH_DIGITS: usize = 20;
+
+pub(crate) fn too_many_digits(context: &str) -> Error {
+    Error::InvalidValue(format!(
+        "Invalid {} length, more than {} digits",
+        context, MAX_LENGTH_DIGITS
+    ))
 }
 
-impl<'a, R: Read> Deserialer<R> {
-    pub fn new(stream: R) -> Deserialer<R> {
-        Deserialer { stream }
+impl<R: Read> Deserializer<R> {
+    pub fn new(stream: R) -> Deserializer<R> {
+        Deserializer {
+            stream,
+            pending: vec![],
+            replay: 0,
+            keep_frame: true,
+            frame_bytes: 0,
+            line_start: true,
+            consumed: 0,
+            unknown_types: false,
+            strict_resp3: false,
+            unique_map_keys: false,
+            raw_doubles: false,
+            max_length: DEFAULT_MAX_LENGTH,
+            max_elements: usize::MAX,
+            max_frame_bytes: usize::MAX,
+            bulk_capacity_hint: DEFAULT_BULK_CAPACITY_HINT,
+            buffer_capacity: 1,
+            depth: 0,
+            stats: None,
+            interner: None,
+            observer: None,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Enables collecting `Stats` about the parsed frames.
+    pub fn with_stats(mut self, enabled: bool) -> Self {
+        self.stats = enabled.then(Stats::default);
+        self
+    }
+
+    /// Returns the collected statistics, `None` unless enabled with
+    /// `with_stats`.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// Returns how many bytes of the stream were consumed so far, by frames
+    /// parsed successfully or not and by `recover_next` skipping ahead. Bytes
+    /// read ahead but not parsed yet don't count.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Rejects the RESP2 null encodings, `$-1` and `*-1`, to check a server
+    /// speaks clean RESP3 where null is always `_`.
+    pub fn with_strict_resp3(mut self, enabled: bool) -> Self {
+        self.strict_resp3 = enabled;
+        self
+    }
+
+    /// Rejects maps, attributes included, holding the same key twice. The
+    /// spec allows it, but it's usually a server bug. Keys are compared one
+    /// by one, which gets slow for huge maps. Not checked by `parse_events`.
+    pub fn with_unique_map_keys(mut self, enabled: bool) -> Self {
+        self.unique_map_keys = enabled;
+        self
+    }
+
+    /// Parses doubles to `Value::RawDouble`, keeping their text so they're
+    /// serialized back exactly as received.
+    pub fn with_raw_doubles(mut self, enabled: bool) -> Self {
+        self.raw_doubles = enabled;
+        self
+    }
+
+    /// Caps the length of bulk string bodies and of the lines holding simple
+    /// strings, errors and integers, so a hostile peer can't make the parser
+    /// buffer unbounded amounts of data.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Caps the number of elements an array or map may declare. Unbounded by
+    /// default.
+    pub fn with_max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = max_elements;
+        self
+    }
+
+    /// Caps the encoded size of a whole top-level frame, nested parts
+    /// included. Parsing stops as soon as the frame grows past it. Unbounded
+    /// by default.
+    pub fn with_max_frame_bytes(mut self, max_frame_bytes: usize) -> Self {
+        self.max_frame_bytes = max_frame_bytes;
+        self
+    }
+
+    /// Sets the size up to which a bulk body is allocated in one go from its
+    /// declared length. Bigger bodies start from this capacity and grow as
+    /// their bytes arrive, so a bogus length can't reserve a huge buffer.
+    pub fn with_bulk_capacity_hint(mut self, hint: usize) -> Self {
+        self.bulk_capacity_hint = hint;
+        self
+    }
+
+    /// Sets how many bytes are requested from the stream per read. By default
+    /// the stream is read one byte at a time, so nothing past the end of a
+    /// frame is ever consumed. With a bigger buffer, which saves syscalls on
+    /// sockets and files, bytes read ahead are kept for the next frame and
+    /// only handed back by `into_inner_with_buffer`.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = capacity.max(1);
+        self
+    }
+
+    /// Installs hooks called as frames are parsed. Without one, parsing
+    /// doesn't pay for them.
+    pub fn with_observer<O: ParseObserver + Send + 'static>(mut self, observer: O) -> Self {
+        self.observer = Some(Observer(Box::new(observer)));
+        self
+    }
+
+    /// Makes `parse_shared` reuse one allocation for identical bulk bodies,
+    /// across all the frames read by this deserializer. Bodies no parsed
+    /// value refers to anymore are eventually forgotten, see `Interner`.
+    pub fn with_interning(mut self, enabled: bool) -> Self {
+        self.interner = enabled.then(Interner::new);
+        self
+    }
+
+    fn check_elements(&self, length: usize) -> Result<()> {
+        if length > self.max_elements {
+            return Err(Error::LimitExceeded(format!(
+                "Number of elements exceeds the limit of {}",
+                self.max_elements
+            )));
+        }
+        Ok(())
+    }
+
+    // `size` is how many bytes the frame would span.
+    fn check_frame_bytes(&self, size: usize) -> Result<()> {
+        if size > self.max_frame_bytes {
+            return Err(Error::LimitExceeded(format!(
+                "Frame size exceeds the limit of {} bytes",
+                self.max_frame_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_length(&self, length: usize) -> Result<()> {
+        if length > self.max_length {
+            return Err(Error::LimitExceeded(format!(
+                "Length exceeds the limit of {} bytes",
+                self.max_length
+            )));
+        }
+        Ok(())
+    }
+
+    /// Captures frames with an unrecognized type prefix as `Value::Unknown`
+    /// (the rest of their line) instead of failing, e.g. for a proxy that has
+    /// to forward types introduced by newer servers.
+    pub fn with_unknown_types(mut self, allow: bool) -> Self {
+        self.unknown_types = allow;
+        self
+    }
+
+    /// Parses frames starting with `prefix` by passing the rest of their line
+    /// to `handler`, e.g. to try out a protocol extension. Built-in types
+    /// take precedence, registering one of their prefixes has no effect.
+    /// `parse_events` reports these frames with `on_unknown`.
+    pub fn register_type<F>(mut self, prefix: u8, handler: F) -> Self
+    where
+        F: FnMut(&[u8]) -> Result<Value> + Send + 'static,
+    {
+        self.handlers.insert(prefix, TypeHandler(Box::new(handler)));
+        self
+    }
+
+    fn parse_registered(&mut self, prefix: u8) -> Result<Value> {
+        let line = self.read_line()?;
+        match self.handlers.get_mut(&prefix) {
+            Some(TypeHandler(handler)) => handler(&line),
+            None => Err(Error::InvalidValue(format!("Invalid character {}", prefix))),
+        }
+    }
+
+    /// Returns the underlying reader.
+    ///
+    /// Bytes already pulled from it but not parsed yet (a peeked type prefix
+    /// or a frame interrupted by a timeout) are dropped, use
+    /// `into_inner_with_buffer` to keep them.
+    pub fn into_inner(self) -> R {
+        self.stream
+    }
+
+    /// Returns the underlying reader along with the bytes read from it that
+    /// weren't consumed by a completed `parse`.
+    pub fn into_inner_with_buffer(self) -> (R, Vec<u8>) {
+        (self.stream, self.pending)
     }
 
     fn peek_byte(&mut self) -> Result<u8> {
-        let mut buf = [0; 1];
-        if 1 != self.stream.read(&mut buf).map_err(Error::IoError)? {
+        if let Some(&c) = self.pending.get(self.replay) {
+            return Ok(c);
+        }
+        if !self.keep_frame {
+            // Everything buffered was consumed.
+            self.pending.clear();
+            self.replay = 0;
+        }
+        let start = self.pending.len();
+        self.pending.resize(start + self.buffer_capacity, 0);
+        let read = loop {
+            match self.stream.read(&mut self.pending[start..]) {
+                // A signal interrupted the read before any data came in.
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Ok(read) => break read,
+                Err(e) => {
+                    self.pending.truncate(start);
+                    return Err(Error::from_io(e));
+                }
+            }
+        };
+        self.pending.truncate(start + read);
+        if read == 0 {
             return Err(Error::EndOfStream);
         }
-        Ok(buf[0])
+        Ok(self.pending[start])
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        self.check_frame_bytes(self.frame_bytes + 1)?;
+        let c = self.peek_byte()?;
+        self.replay += 1;
+        self.frame_bytes += 1;
+        self.line_start = c == b'\n';
+        Ok(c)
     }
 
     fn check_ending(&mut self) -> Result<()> {
-        if self.peek_byte()? != b'\n' {
-            return Err(Error::InvalidValue(
-                "Integer does not end with \\r\\n".to_string(),
-            ));
+        match self.read_byte()? {
+            b'\n' => Ok(()),
+            c => Err(lone_carriage_return(c)),
         }
-        Ok(())
     }
 
-    fn parse_string(&mut self) -> Result<String> {
+    fn read_line(&mut self) -> Result<Vec<u8>> {
         let mut result = vec![];
         loop {
-            match self.peek_byte()? {
+            match self.read_byte()? {
                 b'\r' => {
                     self.check_ending()?;
-                    let out_str = String::from_utf8(result).map_err(|_| {
-                        Error::InvalidValue("Non UTF-8 integer encoding".to_string())
-                    })?;
-                    return Ok(out_str);
+                    return Ok(result);
                 }
                 b'\n' => {
                     return Err(Error::InvalidValue("String contain \\n".to_string()));
                 }
                 c => {
+                    self.check_length(result.len() + 1)?;
                     result.push(c);
                 }
             }
         }
     }
 
+    fn parse_string(&mut self) -> Result<String> {
+        let result = self.read_line()?;
+        String::from_utf8(result)
+            .map_err(|_| Error::InvalidValue("Non UTF-8 integer encoding".to_string()))
+    }
+
     fn parse_error(&mut self) -> Result<String> {
         self.parse_string()
     }
@@ -74,175 +476,2180 @@ impl<'a, R: Read> Deserialer<R> {
     fn parse_integer(&mut self) -> Result<i64> {
         let mut result = vec![];
         loop {
-            match self.peek_byte()? {
+            match self.read_byte()? {
                 b'\r' => {
                     self.check_ending()?;
                     let len_str = String::from_utf8(result).map_err(|_| {
                         Error::InvalidValue("Non UTF-8 integer encoding".to_string())
                     })?;
-                    let len_int = len_str.parse::<i64>().map_err(|_| {
-                        Error::InvalidValue(format!("Can't parse `{}` as integer", len_str))
-                    })?;
-
-                    return Ok(len_int);
+                    return integer_from_str(&len_str);
                 }
                 c => {
+                    self.check_length(result.len() + 1)?;
                     result.push(c);
                 }
             }
         }
     }
 
-    fn parse_bulk(&mut self) -> Result<Vec<u8>> {
-        let length = self.parse_integer()?;
-        let mut resutt = vec![];
-        for _ in 0..length {
-            let c = self.peek_byte()?;
-            resutt.push(c);
+    fn parse_double(&mut self) -> Result<f64> {
+        let line = self.parse_string()?;
+        double_from_str(&line)
+    }
+
+    // RESP3 null has no payload, `_` is directly followed by CRLF.
+    fn parse_null(&mut self) -> Result<()> {
+        match self.read_byte()? {
+            b'\r' => self.check_ending(),
+            c => Err(Error::InvalidValue(format!(
+                "Expected CRLF after null, found `{}`",
+                c.escape_ascii()
+            ))),
+        }
+    }
+
+    fn parse_big_number(&mut self) -> Result<String> {
+        let line = self.parse_string()?;
+        check_big_number(&line)?;
+        Ok(line)
+    }
+
+    // Reads the length header of an aggregate or bulk string. Unlike
+    // `parse_integer` this stops at the first byte that can't be part of the
+    // length, so a body glued to the header is reported as such. `-1` is only
+    // accepted, as `None`, for the types with a RESP2 null encoding.
+    fn parse_length(&mut self, context: &str, nullable: bool) -> Result<Option<usize>> {
+        let mut result = String::new();
+        loop {
+            match self.read_byte()? {
+                b'\r' => {
+                    self.check_ending()?;
+                    break;
+                }
+                c if c.is_ascii_digit() || (c == b'-' && result.is_empty()) => {
+                    if result.len() == MAX_LENGTH_DIGITS {
+                        return Err(too_many_digits(context));
+                    }
+                    self.check_length(result.len() + 1)?;
+                    result.push(c as char);
+                }
+                c => {
+                    return Err(Error::InvalidValue(format!(
+                        "Expected CRLF after {} length, found `{}`",
+                        context,
+                        c.escape_ascii()
+                    )));
+                }
+            }
         }
-        if self.peek_byte()? != b'\r' {
-            return Err(Error::InvalidValue(
-                "Integer does not end with \\r\\n".to_string(),
-            ));
+        match result.parse::<i64>() {
+            Ok(-1) if nullable => Ok(None),
+            Ok(length) if length >= 0 => Ok(Some(length as usize)),
+            _ => Err(Error::InvalidValue(format!(
+                "Invalid {} length `{}`",
+                context, result
+            ))),
+        }
+    }
+
+    fn parse_bulk(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.peek_byte()? == b'?' {
+            self.read_byte()?;
+            return self.parse_streamed_bulk().map(Some);
         }
-        self.check_ending()?;
-        Ok(resutt)
+        self.parse_blob("bulk", true)
     }
 
-    fn parse_array(&mut self) -> Result<Vec<Value>> {
-        let length = self.parse_integer()?;
+    // RESP3 streamed strings: `$?` followed by `;N` chunks until `;0`. The
+    // chunks are concatenated into a single body.
+    fn parse_streamed_bulk(&mut self) -> Result<Vec<u8>> {
+        match self.read_byte()? {
+            b'\r' => self.check_ending()?,
+            c => {
+                return Err(Error::InvalidValue(format!(
+                    "Expected CRLF after `$?`, found `{}`",
+                    c.escape_ascii()
+                )))
+            }
+        }
         let mut result = vec![];
+        loop {
+            match self.read_byte()? {
+                b';' => {}
+                c => {
+                    return Err(Error::InvalidValue(format!(
+                        "Expected `;` before streamed bulk chunk, found `{}`",
+                        c.escape_ascii()
+                    )))
+                }
+            }
+            let length = self
+                .parse_length("streamed bulk chunk", false)?
+                .unwrap_or_default();
+            if length == 0 {
+                break;
+            }
+            self.check_length(result.len().saturating_add(length))?;
+            self.read_body(&mut result, "streamed bulk chunk", length)?;
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.largest_bulk = stats.largest_bulk.max(result.len());
+        }
+        Ok(result)
+    }
+
+    fn parse_bulk_error(&mut self) -> Result<Vec<u8>> {
+        Ok(self.parse_blob("bulk error", false)?.unwrap_or_default())
+    }
+
+    fn parse_verbatim(&mut self) -> Result<Vec<u8>> {
+        Ok(self
+            .parse_blob("verbatim string", false)?
+            .unwrap_or_default())
+    }
+
+    // Reads a length prefixed body, shared by the RESP3 blob types.
+    fn parse_blob(&mut self, context: &str, nullable: bool) -> Result<Option<Vec<u8>>> {
+        let length = match self.parse_length(context, nullable)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        self.check_length(length)?;
+        if let Some(stats) = &mut self.stats {
+            stats.largest_bulk = stats.largest_bulk.max(length);
+        }
+        let mut resutt = Vec::with_capacity(length.min(self.bulk_capacity_hint));
+        self.read_body(&mut resutt, context, length)?;
+        Ok(Some(resutt))
+    }
+
+    // Appends `length` bytes to `body` and checks they're followed by CRLF.
+    fn read_body(&mut self, body: &mut Vec<u8>, context: &str, length: usize) -> Result<()> {
+        // Don't bother reading a body that can't fit.
+        self.check_frame_bytes(self.frame_bytes.saturating_add(length).saturating_add(2))?;
         for _ in 0..length {
-            let value = self.parse()?;
+            let c = self.read_byte()?;
+            body.push(c);
+        }
+        let ending = [self.read_byte()?, self.read_byte()?];
+        if &ending != b"\r\n" {
+            return Err(body_length_mismatch(context, length, &ending));
+        }
+        Ok(())
+    }
+
+    fn parse_array<T>(
+        &mut self,
+        mut element: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Option<Vec<T>>> {
+        let length = match self.parse_length("array", true)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        self.check_elements(length)?;
+        let mut result = Vec::with_capacity(length.min(MAX_PREALLOCATED_ELEMENTS));
+        for i in 0..length {
+            self.check_element(i)?;
+            let value = element(self)?;
             result.push(value);
         }
-        Ok(result)
+        Ok(Some(result))
     }
 
-    fn parse(&mut self) -> Result<Value> {
-        match self.peek_byte()? {
-            b'+' => Ok(Value::String(self.parse_string()?)),
-            b'-' => Ok(Value::String(self.parse_error()?)),
-            b':' => Ok(Value::Integer(self.parse_integer()?)),
-            b'$' => Ok(Value::BulkString(self.parse_bulk()?)),
-            b'*' => Ok(Value::Array(self.parse_array()?)),
-            c => Err(Error::InvalidValue(format!("Invalid character {}", c))),
+    // A stray CRLF in place of an element usually means the sender
+    // miscounted, say so rather than complaining about the prefix.
+    fn check_element(&mut self, index: usize) -> Result<()> {
+        if matches!(self.peek_byte()?, b'\r' | b'\n') {
+            return Err(Error::InvalidValue(format!(
+                "Expected element {} of array, found line terminator",
+                index + 1
+            )));
         }
+        Ok(())
     }
-}
 
-pub fn from_stream<R: Read>(stream: R) -> Result<Value> {
-    let mut d = Deserialer::new(stream);
-    d.parse()
-}
+    fn parse_push(&mut self) -> Result<Vec<Value>> {
+        self.parse_elements("push", Self::parse_value)
+    }
 
-pub fn from_bytes(data: &[u8]) -> Result<Value> {
-    from_stream(data)
-}
+    fn parse_set(&mut self) -> Result<Vec<Value>> {
+        self.parse_elements("set", Self::parse_value)
+    }
 
-pub fn from_string(data: &str) -> Result<Value> {
-    from_bytes(data.as_bytes())
-}
+    // Elements of the aggregates without a null encoding.
+    fn parse_elements<T>(
+        &mut self,
+        context: &str,
+        mut element: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let length = self.parse_length(context, false)?.unwrap_or_default();
+        self.check_elements(length)?;
+        let mut result = Vec::with_capacity(length.min(MAX_PREALLOCATED_ELEMENTS));
+        for _ in 0..length {
+            let value = element(self)?;
+            result.push(value);
+        }
+        Ok(result)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn parse_map<T: PartialEq + fmt::Display>(
+        &mut self,
+        mut element: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<(T, T)>> {
+        let length = self.parse_length("map", false)?.unwrap_or_default();
+        self.check_elements(length)?;
+        let mut result = Vec::with_capacity(length.min(MAX_PREALLOCATED_ELEMENTS));
+        for _ in 0..length {
+            let key = element(self)?;
+            if self.unique_map_keys && result.iter().any(|(k, _)| k == &key) {
+                return Err(Error::InvalidValue(format!("Duplicate map key {}", key)));
+            }
+            let value = element(self)?;
+            result.push((key, value));
+        }
+        Ok(result)
+    }
 
-    fn setup_int(data: &str) -> Result<i64> {
-        let mut d = Deserialer::new(data.as_bytes());
-        d.parse_integer()
+    fn parse_attributes(&mut self) -> Result<Value> {
+        let attributes = Value::Map(self.parse_map(Self::parse_value)?);
+        let value = self.parse_value()?;
+        Ok(Value::WithAttributes {
+            attributes: Box::new(attributes),
+            value: Box::new(value),
+        })
     }
 
-    fn setup_string(data: &str) -> Result<String> {
-        let mut d = Deserialer::new(data.as_bytes());
-        d.parse_string()
+    fn parse_value(&mut self) -> Result<Value> {
+        self.enter()?;
+        let result = self.dispatch_value();
+        self.leave(result.is_ok());
+        result
     }
 
-    fn setup_bulk(data: &str) -> Result<Vec<u8>> {
-        let mut d = Deserialer::new(data.as_bytes());
-        d.parse_bulk()
+    // Bookkeeping around each value, shared by `parse_value` and `emit_value`.
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if let Some(stats) = &mut self.stats {
+            stats.max_depth = stats.max_depth.max(self.depth);
+        }
+        if self.observer.is_some() {
+            let prefix = match self.peek_byte() {
+                Ok(prefix) => prefix,
+                Err(e) => {
+                    self.depth -= 1;
+                    return Err(e);
+                }
+            };
+            if let Some(Observer(observer)) = &mut self.observer {
+                observer.on_value_start(prefix, self.depth);
+            }
+        }
+        Ok(())
     }
-    #[test]
-    fn parse_integer() {
-        let result = setup_int("1234567890\r\n");
-        assert!(result.is_ok(), "{:?}", result.err().unwrap());
-        let result = result.unwrap();
-        let correct = 1234567890;
-        assert_eq!(result, correct);
+
+    fn leave(&mut self, parsed: bool) {
+        if let (true, Some(Observer(observer))) = (parsed, &mut self.observer) {
+            observer.on_value_end(self.depth);
+        }
+        self.depth -= 1;
     }
 
-    #[test]
-    fn parse_negative_integer() {
-        let result = setup_int("-1234567890\r\n");
-        assert!(result.is_ok(), "{:?}", result.err().unwrap());
-        let result = result.unwrap();
-        let correct = -1234567890;
-        assert_eq!(result, correct);
+    fn resp2_null(&self, kind: NullKind) -> Result<Value> {
+        if self.strict_resp3 {
+            let encoding = match kind {
+                NullKind::Array => "*-1",
+                _ => "$-1",
+            };
+            return Err(Error::InvalidValue(format!(
+                "RESP2 null `{}` isn't allowed in strict RESP3 mode",
+                encoding
+            )));
+        }
+        Ok(Value::Null(kind))
     }
 
-    #[test]
-    fn parse_zero() {
-        let result = setup_int("0\r\n");
-        assert!(result.is_ok(), "{:?}", result.err().unwrap());
-        let result = result.unwrap();
-        let correct = 0;
-        assert_eq!(result, correct);
+    fn dispatch_value(&mut self) -> Result<Value> {
+        match self.read_byte()? {
+            b'+' => Ok(Value::String(self.parse_string()?)),
+            b'-' => Ok(Value::Error(self.parse_error()?)),
+            b':' => Ok(Value::Integer(self.parse_integer()?)),
+            b'$' => match self.parse_bulk()? {
+                Some(b) => Ok(Value::BulkString(b)),
+                None => self.resp2_null(NullKind::BulkString),
+            },
+            b'*' => match self.parse_array(Self::parse_value)? {
+                Some(items) => Ok(Value::Array(items)),
+                None => self.resp2_null(NullKind::Array),
+            },
+            b',' if self.raw_doubles => {
+                let raw = self.parse_string()?;
+                Ok(Value::RawDouble {
+                    value: double_from_str(&raw)?,
+                    raw,
+                })
+            }
+            b',' => Ok(Value::Double(self.parse_double()?)),
+            b'(' => Ok(Value::BigNumber(self.parse_big_number()?)),
+            b'!' => Ok(Value::BulkError(self.parse_bulk_error()?)),
+            b'=' => {
+                let body = self.parse_verbatim()?;
+                let (format, data) = split_verbatim(&body)?;
+                Ok(Value::Verbatim {
+                    format: format.to_string(),
+                    data: data.to_vec(),
+                })
+            }
+            b'>' => Ok(Value::Array(self.parse_push()?)),
+            b'%' => Ok(Value::Map(self.parse_map(Self::parse_value)?)),
+            b'~' => Ok(Value::Set(self.parse_set()?)),
+            b'|' => self.parse_attributes(),
+            b'_' => {
+                self.parse_null()?;
+                Ok(Value::Null(NullKind::Resp3))
+            }
+            b'\r' | b'\n' => Err(Error::InvalidValue(
+                "Expected a type prefix, found line terminator".to_string(),
+            )),
+            prefix if self.handlers.contains_key(&prefix) => self.parse_registered(prefix),
+            prefix if self.unknown_types => Ok(Value::Unknown {
+                prefix,
+                raw_line: self.read_line()?,
+            }),
+            c => Err(Error::InvalidValue(format!("Invalid character {}", c))),
+        }
     }
 
-    #[test]
-    fn parse_invalid_integer() {
-        let data = "r\r\n";
-        let result = setup_int(&data);
-        assert!(
-            result.is_err(),
-            "String {} shouldnt parse to integer. Found: {:?}",
-            data,
-            result.unwrap()
-        );
+    fn parse_shared_value(&mut self) -> Result<SharedValue> {
+        self.enter()?;
+        let result = self.dispatch_shared();
+        self.leave(result.is_ok());
+        result
     }
 
-    #[test]
-    fn parse_integer_end_of_stream() {
-        let data = "8122\r";
-        let result = setup_int(&data);
-        assert!(
-            result.is_err(),
-            "String {} should raise an error. Found: {:?}",
-            data,
-            result.unwrap()
-        );
+    // Counterpart of `dispatch_value` handing out reference counted bulk
+    // bodies, interned as they're read. Types that can't hold one are parsed
+    // as usual.
+    fn dispatch_shared(&mut self) -> Result<SharedValue> {
+        match self.peek_byte()? {
+            b'$' | b'*' | b'>' | b'%' | b'~' | b'|' => {}
+            _ => return Ok(SharedValue::Other(self.dispatch_value()?)),
+        }
+        match self.read_byte()? {
+            b'$' => match self.parse_bulk()? {
+                Some(b) => Ok(SharedValue::BulkString(self.share(b))),
+                None => Ok(SharedValue::Other(self.resp2_null(NullKind::BulkString)?)),
+            },
+            b'*' => match self.parse_array(Self::parse_shared_value)? {
+                Some(items) => Ok(SharedValue::Array(items)),
+                None => Ok(SharedValue::Other(self.resp2_null(NullKind::Array)?)),
+            },
+            b'>' => Ok(SharedValue::Array(
+                self.parse_elements("push", Self::parse_shared_value)?,
+            )),
+            b'%' => Ok(SharedValue::Map(self.parse_map(Self::parse_shared_value)?)),
+            b'~' => Ok(SharedValue::Set(
+                self.parse_elements("set", Self::parse_shared_value)?,
+            )),
+            // `|`, the only prefix left.
+            _ => {
+                let attributes = SharedValue::Map(self.parse_map(Self::parse_shared_value)?);
+                let value = self.parse_shared_value()?;
+                Ok(SharedValue::WithAttributes {
+                    attributes: Box::new(attributes),
+                    value: Box::new(value),
+                })
+            }
+        }
     }
 
-    // TODO: write more tests for string
-    #[test]
-    fn parse_string() {
-        let data = "OK\r\n";
-        let result = setup_string(&data);
-        assert!(result.is_ok(), "{:?}", result.err().unwrap());
-        let result = result.unwrap();
-        let correct = "OK".to_string();
-        assert_eq!(result, correct);
+    fn share(&mut self, body: Vec<u8>) -> Arc<[u8]> {
+        match &mut self.interner {
+            Some(interner) => interner.intern(&body),
+            None => body.into(),
+        }
     }
 
-    #[test]
-    fn parse_string_end_of_stream() {
-        let data = "OK\r";
-        let result = setup_int(&data);
-        assert!(
-            result.is_err(),
-            "String {} should raise an error. Found: {:?}",
-            data,
-            result.unwrap()
-        );
+    fn emit_value<S: ValueSink>(&mut self, sink: &mut S) -> Result<()> {
+        self.enter()?;
+        let result = self.dispatch_event(sink);
+        self.leave(result.is_ok());
+        result
     }
 
-    #[test]
+    // Counterpart of `dispatch_value` reporting to a sink instead of building
+    // a value.
+    fn dispatch_event<S: ValueSink>(&mut self, sink: &mut S) -> Result<()> {
+        match self.read_byte()? {
+            b'+' => sink.on_string(&self.parse_string()?),
+            b'-' => sink.on_error(&self.parse_error()?),
+            b':' => sink.on_integer(self.parse_integer()?),
+            b'$' => match self.parse_bulk()? {
+                Some(b) => sink.on_bulk(&b),
+                None => {
+                    self.resp2_null(NullKind::BulkString)?;
+                    sink.on_null();
+                }
+            },
+            b'*' => match self.parse_length("array", true)? {
+                Some(length) => {
+                    self.check_elements(length)?;
+                    sink.on_array_start(length);
+                    for i in 0..length {
+                        self.check_element(i)?;
+                        self.emit_value(sink)?;
+                    }
+                    sink.on_array_end();
+                }
+                None => {
+                    self.resp2_null(NullKind::Array)?;
+                    sink.on_null();
+                }
+            },
+            b',' => sink.on_double(self.parse_double()?),
+            b'(' => sink.on_big_number(&self.parse_big_number()?),
+            b'!' => sink.on_bulk_error(&self.parse_bulk_error()?),
+            b'=' => {
+                let body = self.parse_verbatim()?;
+                let (format, data) = split_verbatim(&body)?;
+                sink.on_verbatim(format, data);
+            }
+            b'>' => {
+                let length = self.parse_length("push", false)?.unwrap_or_default();
+                self.check_elements(length)?;
+                sink.on_array_start(length);
+                for _ in 0..length {
+                    self.emit_value(sink)?;
+                }
+                sink.on_array_end();
+            }
+            b'~' => {
+                let length = self.parse_length("set", false)?.unwrap_or_default();
+                self.check_elements(length)?;
+                sink.on_set_start(length);
+                for _ in 0..length {
+                    self.emit_value(sink)?;
+                }
+                sink.on_set_end();
+            }
+            b'%' => {
+                let length = self.parse_length("map", false)?.unwrap_or_default();
+                self.check_elements(length)?;
+                sink.on_map_start(length);
+                for _ in 0..length * 2 {
+                    self.emit_value(sink)?;
+                }
+                sink.on_map_end();
+            }
+            b'|' => {
+                let length = self.parse_length("map", false)?.unwrap_or_default();
+                self.check_elements(length)?;
+                sink.on_attributes_start(length);
+                for _ in 0..length * 2 {
+                    self.emit_value(sink)?;
+                }
+                sink.on_attributes_end();
+                self.emit_value(sink)?;
+            }
+            b'_' => {
+                self.parse_null()?;
+                sink.on_null();
+            }
+            b'\r' | b'\n' => {
+                return Err(Error::InvalidValue(
+                    "Expected a type prefix, found line terminator".to_string(),
+                ))
+            }
+            prefix if self.unknown_types || self.handlers.contains_key(&prefix) => {
+                sink.on_unknown(prefix, &self.read_line()?)
+            }
+            c => return Err(Error::InvalidValue(format!("Invalid character {}", c))),
+        }
+        Ok(())
+    }
+
+    fn parse_frame_value(&mut self) -> Result<Frame> {
+        match self.peek_byte()? {
+            b'>' => {
+                self.read_byte()?;
+                Ok(Frame::Push(self.parse_push()?))
+            }
+            b'|' => {
+                self.read_byte()?;
+                let attributes = self.parse_map(Self::parse_value)?;
+                let frame = self.parse_frame_value()?;
+                Ok(Frame::Attribute {
+                    attributes,
+                    frame: Box::new(frame),
+                })
+            }
+            _ => Ok(Frame::Value(self.parse_value()?)),
+        }
+    }
+
+    // Runs `parse` over one whole frame. On `Error::Timeout` the bytes read so
+    // far are retained and replayed by the next call, if `keep_frame` is set.
+    fn complete<T>(
+        &mut self,
+        keep_frame: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.replay = 0;
+        self.frame_bytes = 0;
+        self.keep_frame = keep_frame;
+        if let Some(Observer(observer)) = &mut self.observer {
+            observer.on_frame_start();
+        }
+        let result = parse(self);
+        if let Some(Observer(observer)) = &mut self.observer {
+            match &result {
+                Ok(_) => observer.on_frame_end(self.frame_bytes),
+                Err(e) => observer.on_error(e),
+            }
+        }
+        if !(keep_frame && matches!(result, Err(Error::Timeout(_)))) {
+            // Keep a byte that was peeked but not consumed.
+            self.pending.drain(..self.replay);
+            self.consumed += self.frame_bytes as u64;
+            if let Some(stats) = &mut self.stats {
+                stats.bytes += self.frame_bytes as u64;
+                stats.frames += result.is_ok() as u64;
+            }
+        }
+        self.replay = 0;
+        self.frame_bytes = 0;
+        self.keep_frame = true;
+        result
+    }
+
+    /// Parses the next value from the stream.
+    ///
+    /// On `Error::Timeout` the partially read frame is retained and the next
+    /// call picks it up where the stream left off.
+    pub fn parse(&mut self) -> Result<Value> {
+        self.complete(true, Self::parse_value)
+    }
+
+    /// Parses the next frame, reporting its parts to `sink` instead of
+    /// building a `Value`.
+    ///
+    /// Bytes are dropped as soon as they're parsed, so unlike `parse` this
+    /// doesn't buffer the whole frame. The sink already got the start of a
+    /// frame interrupted by `Error::Timeout`, so it isn't resumed: the rest of
+    /// the frame is left in the stream, see `recover_next`.
+    pub fn parse_events<S: ValueSink>(&mut self, sink: &mut S) -> Result<()> {
+        self.complete(false, |d| d.emit_value(sink))
+    }
+
+    /// Reads the next frame and returns its raw bytes, e.g. to forward it
+    /// elsewhere. The frame is validated the same way `parse` does.
+    pub fn read_raw(&mut self) -> Result<Vec<u8>> {
+        self.complete(true, |d| {
+            d.parse_value()?;
+            Ok(d.pending[..d.replay].to_vec())
+        })
+    }
+
+    /// Parses the next value with reference counted bulk bodies, shared
+    /// between identical bodies when interning is enabled.
+    pub fn parse_shared(&mut self) -> Result<SharedValue> {
+        self.complete(true, Self::parse_shared_value)
+    }
+
+    /// Parses the next frame from the stream, keeping pushes and attributes
+    /// apart from regular replies.
+    pub fn parse_frame(&mut self) -> Result<Frame> {
+        self.complete(true, Self::parse_frame_value)
+    }
+
+    /// Skips ahead to the next line starting with a known type prefix and
+    /// parses from there. Meant to be called after the iterator yielded an
+    /// error, to carry on with the rest of a damaged stream.
+    pub fn recover_next(&mut self) -> Option<Result<Value>> {
+        loop {
+            match self.peek_byte() {
+                Ok(_) => {}
+                Err(Error::EndOfStream) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+            // Find where the next frame starts in what's buffered, then drop
+            // everything before it at once, nothing skipped is ever replayed.
+            let mut offset = 0;
+            while let Some(&c) = self.pending.get(offset) {
+                if self.line_start && ValueType::from_prefix(c).is_some() {
+                    break;
+                }
+                self.line_start = c == b'\n';
+                offset += 1;
+            }
+            self.pending.drain(..offset);
+            self.consumed += offset as u64;
+            if let Some(stats) = &mut self.stats {
+                stats.bytes += offset as u64;
+            }
+            if !self.pending.is_empty() {
+                break;
+            }
+        }
+        self.next()
+    }
+
+    /// Returns the type of the next value without consuming it.
+    pub fn peek_type(&mut self) -> Result<ValueType> {
+        match self.peek_byte()? {
+            b'\r' | b'\n' => Err(Error::InvalidValue(
+                "Expected a type prefix, found line terminator".to_string(),
+            )),
+            c => match ValueType::from_prefix(c) {
+                Some(value_type) => Ok(value_type),
+                None if self.unknown_types || self.handlers.contains_key(&c) => {
+                    Ok(ValueType::Unknown)
+                }
+                None => Err(Error::InvalidValue(format!("Invalid character {}", c))),
+            },
+        }
+    }
+
+    /// Parses the next value, or returns `Ok(None)` if the stream ended
+    /// cleanly between two frames. A stream ending in the middle of a frame
+    /// gives `Error::EndOfStream`.
+    pub fn next_value(&mut self) -> Result<Option<Value>> {
+        match self.peek_byte() {
+            Ok(_) => self.parse().map(Some),
+            Err(Error::EndOfStream) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Passes the values of the stream to `f` one by one, until it returns
+    /// `ControlFlow::Break` or the stream ends cleanly between two frames.
+    /// Frames after the one `f` stopped at are left unread.
+    pub fn for_each_value<F: FnMut(Value) -> ControlFlow<()>>(&mut self, mut f: F) -> Result<()> {
+        while let Some(value) = self.next_value()? {
+            if f(value).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the next value and fails if its type isn't one of `allowed`.
+    /// The whole frame is consumed either way, so the stream stays in sync.
+    pub fn parse_expecting(&mut self, allowed: &[ValueType]) -> Result<Value> {
+        let value_type = self.peek_type()?;
+        let value = self.parse()?;
+        if !allowed.contains(&value_type) {
+            return Err(Error::InvalidValue(format!(
+                "Expected one of {:?}, found {:?}",
+                allowed, value_type
+            )));
+        }
+        Ok(value)
+    }
+}
+
+/// Yields the values of the stream one by one until it ends cleanly between
+/// two frames. A stream ending in the middle of a frame yields
+/// `Error::EndOfStream`.
+impl<R: Read> Iterator for Deserializer<R> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Result<Value>> {
+        self.next_value().transpose()
+    }
+}
+
+pub fn from_stream<R: Read>(stream: R) -> Result<Value> {
+    let mut d = Deserializer::new(stream);
+    match d.peek_byte() {
+        Ok(_) => d.parse(),
+        Err(Error::EndOfStream) => Err(Error::EmptyInput),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parses a single frame from `stream`, reporting its parts to `sink`.
+pub fn parse_events<R: Read, S: ValueSink>(stream: R, sink: &mut S) -> Result<()> {
+    Deserializer::new(stream).parse_events(sink)
+}
+
+/// Reads exactly one frame from `reader` and returns its raw bytes. Nothing
+/// past the end of the frame is read.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    Deserializer::new(reader).read_raw()
+}
+
+/// Parses the first frame in `data`, indexing into the slice rather than
+/// reading it byte by byte. Failures are parsed again by the streaming
+/// parser, so errors and RESP3 streamed strings behave like `from_stream`.
+pub fn from_bytes(data: &[u8]) -> Result<Value> {
+    match from_bytes_borrowed(data) {
+        Ok(value) => Ok(value.into_owned()),
+        Err(_) => from_stream(data),
+    }
+}
+
+// Adapts a byte iterator, such as the one returned by `Read::bytes()`, back
+// into a `Read` so the deserializer can consume it.
+struct ByteIterReader<I> {
+    iter: I,
+    error: Option<IoError>,
+}
+
+impl<I: Iterator<Item = IoResult<u8>>> Read for ByteIterReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            match self.iter.next() {
+                Some(Ok(b)) => {
+                    buf[n] = b;
+                    n += 1;
+                }
+                Some(Err(e)) if n == 0 => return Err(e),
+                // Hand out what we have, the error is reported on the next read.
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+        Ok(n)
+    }
+}
+
+pub fn from_byte_iter<I: Iterator<Item = IoResult<u8>>>(iter: I) -> Result<Value> {
+    from_stream(ByteIterReader { iter, error: None })
+}
+
+/// Parses the first frame in `buf` and moves `buf` past it, so repeated
+/// calls walk through a pipeline. `buf` is left untouched on error.
+pub fn from_slice_advancing(buf: &mut &[u8]) -> Result<Value> {
+    if let Ok((value, consumed)) = parse_prefix(buf) {
+        *buf = &buf[consumed..];
+        return Ok(value.into_owned());
+    }
+    // Reading a slice advances it, and the deserializer never reads past the
+    // end of the frame.
+    let mut rest = *buf;
+    let value = from_stream(&mut rest)?;
+    *buf = rest;
+    Ok(value)
+}
+
+/// Parses the frames of a pipeline buffer, at most `max_frames` of them, and
+/// returns them along with the rest of `data`. A non-empty rest means more
+/// frames remain, to be parsed by calling again on it.
+///
+/// Fails if the buffer ends in the middle of a frame.
+pub fn from_bytes_all(data: &[u8], max_frames: usize) -> Result<(Vec<Value>, &[u8])> {
+    let mut rest = data;
+    let mut result = vec![];
+    while !rest.is_empty() && result.len() < max_frames {
+        result.push(from_slice_advancing(&mut rest)?);
+    }
+    Ok((result, rest))
+}
+
+/// Parses a frame held in a string. RESP is a binary protocol, so this works
+/// on the UTF-8 bytes of `data`: bulk lengths count bytes, not characters.
+pub fn from_string(data: &str) -> Result<Value> {
+    from_bytes(data.as_bytes())
+}
+
+/// Guesses the protocol spoken from the type prefix of the first frame in
+/// `data`: RESP3 for the types RESP2 doesn't have, RESP2 otherwise, though
+/// RESP3 servers use the other prefixes too. `None` if `data` is empty.
+pub fn detect_protocol(data: &[u8]) -> Option<ProtocolVersion> {
+    match data.first()? {
+        b'%' | b'~' | b'>' | b',' | b'#' | b'_' | b'=' | b'(' | b'!' | b'|' => {
+            Some(ProtocolVersion::Resp3)
+        }
+        _ => Some(ProtocolVersion::Resp2),
+    }
+}
+
+fn unescape(data: &str) -> Result<Vec<u8>> {
+    let mut result = vec![];
+    let mut bytes = data.bytes();
+    while let Some(c) = bytes.next() {
+        if c != b'\\' {
+            result.push(c);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'r') => result.push(b'\r'),
+            Some(b'n') => result.push(b'\n'),
+            Some(b't') => result.push(b'\t'),
+            Some(b'0') => result.push(b'\0'),
+            Some(b'\\') => result.push(b'\\'),
+            Some(b'"') => result.push(b'"'),
+            Some(b'x') => {
+                let digits = [bytes.next(), bytes.next()];
+                let byte = match digits {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                result.push(byte.ok_or_else(|| {
+                    Error::InvalidValue("Expected two hex digits after \\x".to_string())
+                })?);
+            }
+            Some(c) => {
+                return Err(Error::InvalidValue(format!(
+                    "Unknown escape `\\{}`",
+                    c.escape_ascii()
+                )))
+            }
+            None => {
+                return Err(Error::InvalidValue(
+                    "Escape at the end of input".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Parses a frame written with escapes, as found in logs: `\r`, `\n`, `\t`,
+/// `\0`, `\\`, `\"` and `\xNN` are replaced before parsing.
+pub fn from_escaped(data: &str) -> Result<Value> {
+    from_bytes(&unescape(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::borrowed::ValueRef;
+    use std::collections::VecDeque;
+    use std::io;
+
+    // Serves each chunk in turn, returning errors in place of chunks.
+    struct MockReader {
+        chunks: VecDeque<io::Result<Vec<u8>>>,
+    }
+
+    impl MockReader {
+        fn new(chunks: Vec<io::Result<Vec<u8>>>) -> MockReader {
+            MockReader {
+                chunks: chunks.into(),
+            }
+        }
+    }
+
+    impl Read for MockReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop_front() {
+                None => Ok(0),
+                Some(Err(e)) => Err(e),
+                Some(Ok(mut chunk)) => {
+                    let n = buf.len().min(chunk.len());
+                    buf[..n].copy_from_slice(&chunk[..n]);
+                    if n < chunk.len() {
+                        self.chunks.push_front(Ok(chunk.split_off(n)));
+                    }
+                    Ok(n)
+                }
+            }
+        }
+    }
+
+    fn setup_int(data: &str) -> Result<i64> {
+        let mut d = Deserializer::new(data.as_bytes());
+        d.parse_integer()
+    }
+
+    fn setup_string(data: &str) -> Result<String> {
+        let mut d = Deserializer::new(data.as_bytes());
+        d.parse_string()
+    }
+
+    fn setup_bulk(data: &str) -> Result<Option<Vec<u8>>> {
+        let mut d = Deserializer::new(data.as_bytes());
+        d.parse_bulk()
+    }
+    #[test]
+    fn parse_integer() {
+        let result = setup_int("1234567890\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = 1234567890;
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_negative_integer() {
+        let result = setup_int("-1234567890\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = -1234567890;
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_zero() {
+        let result = setup_int("0\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = 0;
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_invalid_integer() {
+        let data = "r\r\n";
+        let result = setup_int(data);
+        assert!(
+            result.is_err(),
+            "String {} shouldnt parse to integer. Found: {:?}",
+            data,
+            result.unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_integer_end_of_stream() {
+        let data = "8122\r";
+        let result = setup_int(data);
+        assert!(
+            result.is_err(),
+            "String {} should raise an error. Found: {:?}",
+            data,
+            result.unwrap()
+        );
+    }
+
+    // TODO: write more tests for string
+    #[test]
+    fn parse_string() {
+        let data = "OK\r\n";
+        let result = setup_string(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = "OK".to_string();
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_string_end_of_stream() {
+        let data = "OK\r";
+        let result = setup_int(data);
+        assert!(
+            result.is_err(),
+            "String {} should raise an error. Found: {:?}",
+            data,
+            result.unwrap()
+        );
+    }
+
+    #[test]
     fn parse_bulk_string() {
         let data = "4\r\nECHO\r\n";
-        let result = setup_bulk(&data);
+        let result = setup_bulk(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Some(Vec::from("ECHO".as_bytes()));
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_null_bulk_string() {
+        let data = "-1\r\n";
+        let result = setup_bulk(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn parse_null_array() {
+        let result = from_string("*-1\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        assert_eq!(result, Value::Null(NullKind::Array));
+    }
+
+    #[test]
+    fn parse_from_byte_iter() {
+        let data = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n".to_vec();
+        let result = from_byte_iter(data.iter().map(|&b| Ok(b)));
         assert!(result.is_ok(), "{:?}", result.err().unwrap());
         let result = result.unwrap();
-        let correct = "ECHO".as_bytes();
+        let correct = Value::Array(vec![
+            Value::BulkString(b"ECHO".to_vec()),
+            Value::BulkString(b"hey".to_vec()),
+        ]);
         assert_eq!(result, correct);
     }
+
+    #[test]
+    fn parse_from_byte_iter_error() {
+        let data = b"*2\r\n:1\r\n".iter().map(|&b| Ok(b));
+        let failing = std::iter::once(Err(io::Error::other("boom")));
+        let result = from_byte_iter(data.chain(failing));
+        assert!(
+            matches!(result, Err(Error::IoError(_))),
+            "Expected IoError. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_bulk_length_mismatch() {
+        assert_invalid_value(
+            "$3\r\nhello\r\n",
+            "Bulk body length mismatch: expected CRLF after 3 bytes, found `lo`",
+        );
+        assert_invalid_value(
+            "!2\r\nERR\r\n",
+            "Bulk error body length mismatch: expected CRLF after 2 bytes, found `R\\r`",
+        );
+    }
+
+    #[test]
+    fn parse_interned() {
+        let data = "*4\r\n$5\r\nfield\r\n$1\r\na\r\n$5\r\nfield\r\n$1\r\nb\r\n$5\r\nfield\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_interning(true);
+        let result = d.parse_shared();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let items = match result.unwrap() {
+            SharedValue::Array(items) => items,
+            v => panic!("Expected array. Found: {:?}", v),
+        };
+        match (&items[0], &items[2]) {
+            (SharedValue::BulkString(a), SharedValue::BulkString(b)) => {
+                assert!(std::sync::Arc::ptr_eq(a, b))
+            }
+            v => panic!("Expected bulk strings. Found: {:?}", v),
+        }
+        // Interning carries over to later frames.
+        let result = d.parse_shared();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        match (&items[0], result.unwrap()) {
+            (SharedValue::BulkString(a), SharedValue::BulkString(b)) => {
+                assert!(std::sync::Arc::ptr_eq(a, &b))
+            }
+            v => panic!("Expected bulk strings. Found: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn parse_interned_set() {
+        let data = "|1\r\n$5\r\nfield\r\n:1\r\n~3\r\n$5\r\nfield\r\n$1\r\na\r\n$5\r\nfield\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_interning(true);
+        let result = d.parse_shared();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        assert_eq!(result.to_owned(), from_string(data).unwrap());
+        let (attributes, items) = match result {
+            SharedValue::WithAttributes { attributes, value } => match (*attributes, *value) {
+                (SharedValue::Map(entries), SharedValue::Set(items)) => (entries, items),
+                v => panic!("Expected map and set. Found: {:?}", v),
+            },
+            v => panic!("Expected attributes. Found: {:?}", v),
+        };
+        match (&attributes[0].0, &items[0], &items[2]) {
+            (
+                SharedValue::BulkString(a),
+                SharedValue::BulkString(b),
+                SharedValue::BulkString(c),
+            ) => {
+                assert!(std::sync::Arc::ptr_eq(a, b));
+                assert!(std::sync::Arc::ptr_eq(b, c));
+            }
+            v => panic!("Expected bulk strings. Found: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn parse_string_counts_bytes() {
+        // The emoji is a single character but four bytes.
+        let result = from_string("$6\r\nhi \u{1f600}\r\n");
+        assert!(result.is_err(), "Length in characters shouldn't parse");
+        let result = from_string("$7\r\nhi \u{1f600}\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::BulkString("hi \u{1f600}".as_bytes().to_vec());
+        assert_eq!(result, correct);
+        assert_invalid_value(
+            "$1\r\n\u{1f600}\r\n",
+            "Bulk body length mismatch: expected CRLF after 1 bytes, found `\\x9f\\x98`",
+        );
+    }
+
+    #[test]
+    fn read_raw_frame() {
+        let data = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n:1\r\n";
+        let mut reader = &data[..];
+        let result = read_frame(&mut reader);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        assert_eq!(result, b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n");
+        assert_eq!(reader, b":1\r\n");
+        let result = read_frame(&mut &b"$1\r\nab\r\n"[..]);
+        assert!(
+            matches!(result, Err(Error::InvalidValue(_))),
+            "Expected invalid value error. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_streamed_bulk() {
+        let result = from_string("$?\r\n;5\r\nHello\r\n;6\r\n World\r\n;0\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::BulkString(b"Hello World".to_vec());
+        assert_eq!(result, correct);
+
+        let result = from_string("$?\r\n;0\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::BulkString(vec![]));
+
+        assert_invalid_value(
+            "$?\r\n;4\r\nHello\r\n;0\r\n",
+            "Streamed bulk chunk body length mismatch: expected CRLF after 4 bytes, found `o\\r`",
+        );
+        assert_invalid_value(
+            "$?\r\n5\r\nHello\r\n",
+            "Expected `;` before streamed bulk chunk, found `5`",
+        );
+        assert_invalid_value("$?x\r\n", "Expected CRLF after `$?`, found `x`");
+    }
+
+    #[test]
+    fn parse_strict_resp3_null() {
+        for (data, kind) in [
+            ("$-1\r\n", NullKind::BulkString),
+            ("*-1\r\n", NullKind::Array),
+        ] {
+            let result = from_string(data);
+            assert!(result.is_ok(), "{:?}", result.err().unwrap());
+            assert_eq!(result.unwrap(), Value::Null(kind));
+
+            let mut d = Deserializer::new(data.as_bytes()).with_strict_resp3(true);
+            let result = d.parse();
+            assert!(
+                matches!(result, Err(Error::InvalidValue(_))),
+                "RESP2 null shouldn't parse in strict mode. Found: {:?}",
+                result
+            );
+        }
+        let mut d = Deserializer::new("_\r\n".as_bytes()).with_strict_resp3(true);
+        assert_eq!(d.parse().unwrap(), Value::Null(NullKind::Resp3));
+    }
+
+    #[test]
+    fn parse_array_stray_crlf() {
+        assert_invalid_value(
+            "*1\r\n\r\n",
+            "Expected element 1 of array, found line terminator",
+        );
+        assert_invalid_value(
+            "*2\r\n:1\r\n\r\n:2\r\n",
+            "Expected element 2 of array, found line terminator",
+        );
+    }
+
+    #[test]
+    fn slice_path_matches_stream_path() {
+        let inputs: [&[u8]; 11] = [
+            b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n",
+            b"%1\r\n+a\r\n,2.5\r\n",
+            b"|1\r\n+ttl\r\n:3\r\n(123\r\n",
+            b"$-1\r\n",
+            b"_\r\n",
+            b"!3\r\nERR\r\n",
+            b"$3\r\nhello\r\n",
+            b"*2\r\n:1\r\n",
+            b"*1\r\n\r\n",
+            b":12a\r\n",
+            b"?\r\n",
+        ];
+        // `from_bytes` falls back to the stream parser on error, so compare
+        // the slice parser on its own.
+        for data in inputs {
+            let fast = format!("{:?}", from_bytes_borrowed(data).map(ValueRef::into_owned));
+            let stream = format!("{:?}", from_stream(data));
+            assert_eq!(fast, stream, "for {:?}", data.escape_ascii().to_string());
+        }
+
+        // Streamed strings can't be borrowed, they take the fallback.
+        let data = b"$?\r\n;2\r\nhi\r\n;0\r\n";
+        assert!(from_bytes_borrowed(data).is_err());
+        assert_eq!(from_bytes(data).unwrap(), Value::BulkString(b"hi".to_vec()));
+    }
+
+    #[test]
+    fn parse_events_sum() {
+        #[derive(Default)]
+        struct Sum {
+            total: i64,
+            arrays: usize,
+        }
+
+        impl ValueSink for Sum {
+            fn on_integer(&mut self, i: i64) {
+                self.total += i;
+            }
+
+            fn on_array_start(&mut self, _length: usize) {
+                self.arrays += 1;
+            }
+        }
+
+        let mut data = b"*10000\r\n".to_vec();
+        for i in 0..10000 {
+            data.extend_from_slice(format!(":{}\r\n", i).as_bytes());
+        }
+        let mut sum = Sum::default();
+        let result = parse_events(&data[..], &mut sum);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(sum.total, 49995000);
+        assert_eq!(sum.arrays, 1);
+
+        let mut sum = Sum::default();
+        let result = parse_events(&b"*2\r\n:1\r\n"[..], &mut sum);
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_events_nested() {
+        #[derive(Default)]
+        struct Trace(Vec<String>);
+
+        impl ValueSink for Trace {
+            fn on_bulk(&mut self, b: &[u8]) {
+                self.0.push(String::from_utf8_lossy(b).to_string());
+            }
+
+            fn on_null(&mut self) {
+                self.0.push("null".to_string());
+            }
+
+            fn on_array_start(&mut self, length: usize) {
+                self.0.push(format!("[{}", length));
+            }
+
+            fn on_array_end(&mut self) {
+                self.0.push("]".to_string());
+            }
+
+            fn on_map_start(&mut self, length: usize) {
+                self.0.push(format!("{{{}", length));
+            }
+
+            fn on_map_end(&mut self) {
+                self.0.push("}".to_string());
+            }
+        }
+
+        let mut trace = Trace::default();
+        let data = b"*2\r\n%1\r\n$1\r\na\r\n_\r\n*0\r\n";
+        let result = parse_events(&data[..], &mut trace);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let correct = ["[2", "{1", "a", "null", "}", "[0", "]", "]"];
+        assert_eq!(trace.0, correct);
+    }
+
+    #[test]
+    fn parse_slice_advancing() {
+        let data = b"+OK\r\n$?\r\n;2\r\nhi\r\n;0\r\n";
+        let mut buf = &data[..];
+        let result = from_slice_advancing(&mut buf);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::String("OK".to_string()));
+        assert_eq!(buf, b"$?\r\n;2\r\nhi\r\n;0\r\n");
+        let result = from_slice_advancing(&mut buf);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::BulkString(b"hi".to_vec()));
+        assert!(buf.is_empty());
+
+        let mut buf = &b"*2\r\n:1\r\n"[..];
+        let result = from_slice_advancing(&mut buf);
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+        assert_eq!(buf, b"*2\r\n:1\r\n");
+    }
+
+    #[test]
+    fn observer_sequence() {
+        use std::sync::{Arc, Mutex};
+
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl ParseObserver for Recorder {
+            fn on_frame_start(&mut self) {
+                self.0.lock().unwrap().push("frame".to_string());
+            }
+
+            fn on_frame_end(&mut self, bytes: usize) {
+                self.0.lock().unwrap().push(format!("frame end {}", bytes));
+            }
+
+            fn on_value_start(&mut self, prefix: u8, depth: usize) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", prefix as char, depth));
+            }
+
+            fn on_value_end(&mut self, depth: usize) {
+                self.0.lock().unwrap().push(format!("end {}", depth));
+            }
+
+            fn on_error(&mut self, error: &Error) {
+                self.0.lock().unwrap().push(format!("error {:?}", error));
+            }
+        }
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let data = "*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n:x\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_observer(Recorder(events.clone()));
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let correct = [
+            "frame",
+            "* 1",
+            "$ 2",
+            "end 2",
+            "$ 2",
+            "end 2",
+            "end 1",
+            "frame end 23",
+        ];
+        assert_eq!(*events.lock().unwrap(), correct);
+
+        events.lock().unwrap().clear();
+        assert!(d.parse().is_err());
+        let correct = [
+            "frame",
+            ": 1",
+            "error InvalidValue(\"Can't parse `x` as integer\")",
+        ];
+        assert_eq!(*events.lock().unwrap(), correct);
+    }
+
+    #[test]
+    fn deserializer_is_send() {
+        fn assert_send<T: Send>() {}
+        // Observers and registered handlers mustn't keep a connection from
+        // moving to another thread.
+        assert_send::<Deserializer<std::net::TcpStream>>();
+    }
+
+    #[test]
+    fn parse_set() {
+        let result = from_string("~2\r\n+a\r\n:1\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::Set(vec![Value::String("a".to_string()), Value::Integer(1)]);
+        assert_eq!(result, correct);
+        assert_invalid_value("~-1\r\n", "Invalid set length `-1`");
+    }
+
+    #[test]
+    fn parse_binary_bulk() {
+        // `DUMP` of a small string followed by every byte value, so the body
+        // holds NUL, `\r\n` and bytes that aren't valid UTF-8.
+        let mut blob =
+            b"\x00\x05hello\x09\x00\xb3\x80\x8e\xba\x31\xb2\x43\xbb\r\n\r\n$3\r\n".to_vec();
+        blob.extend(0..=255u8);
+        let mut data = format!("${}\r\n", blob.len()).into_bytes();
+        data.extend_from_slice(&blob);
+        data.extend_from_slice(b"\r\n");
+        let correct = Value::BulkString(blob);
+
+        let result = from_stream(&data[..]);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), correct);
+        let result = from_bytes(&data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), correct);
+    }
+
+    #[test]
+    fn parse_lone_carriage_return() {
+        assert_invalid_value(
+            "+OK\rX",
+            "Found `\\r` not followed by `\\n`, got `X` instead",
+        );
+        assert_invalid_value(
+            ":1\r\r\n",
+            "Found `\\r` not followed by `\\n`, got `\\r` instead",
+        );
+    }
+
+    #[test]
+    fn parse_long_digit_run() {
+        let data = format!("${}\r\n", "9".repeat(100000));
+        assert_invalid_value(&data, "Invalid bulk length, more than 20 digits");
+        let data = format!("*-{}\r\n", "0".repeat(20));
+        assert_invalid_value(&data, "Invalid array length, more than 20 digits");
+        // Leading zeros are fine as long as the run is short.
+        let result = from_string("$0003\r\nhey\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+    }
+
+    #[test]
+    fn parse_empty_input() {
+        for result in [from_bytes(b""), from_stream(&b""[..]), from_string("")] {
+            assert!(
+                matches!(result, Err(Error::EmptyInput)),
+                "Expected empty input. Found: {:?}",
+                result
+            );
+        }
+        let result = from_bytes(b"*2\r\n");
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_verbatim() {
+        let result = from_string("=15\r\ntxt:Some string\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::Verbatim {
+            format: "txt".to_string(),
+            data: b"Some string".to_vec(),
+        };
+        assert_eq!(result, correct);
+        assert_invalid_value(
+            "=5\r\ntxt-a\r\n",
+            "Verbatim string must start with a 3 character format and `:`",
+        );
+        assert_invalid_value(
+            "=2\r\ntx\r\n",
+            "Verbatim string must start with a 3 character format and `:`",
+        );
+        assert_invalid_value(
+            "=7\r\ntx1:abc\r\n",
+            "Verbatim string format must be 3 ASCII letters, got `tx1`",
+        );
+    }
+
+    #[test]
+    fn parse_retries_interrupted() {
+        let mut d = Deserializer::new(MockReader::new(vec![
+            Ok(b":4".to_vec()),
+            Err(io::Error::from(io::ErrorKind::Interrupted)),
+            Ok(b"2\r\n".to_vec()),
+        ]));
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Integer(42));
+    }
+
+    #[test]
+    fn parse_bulk_from_short_reads() {
+        let data = b"$10\r\n0123456789\r\n";
+        let chunks = data.iter().map(|&c| Ok(vec![c])).collect();
+        let result = Deserializer::new(MockReader::new(chunks)).parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::BulkString(b"0123456789".to_vec()));
+
+        let chunks = data[..10].iter().map(|&c| Ok(vec![c])).collect();
+        let result = Deserializer::new(MockReader::new(chunks)).parse();
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream error. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_resp3_null() {
+        let result = from_string("_\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Null(NullKind::Resp3));
+        assert_invalid_value("_x\r\n", "Expected CRLF after null, found `x`");
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let data = "|1\r\n+key-popularity\r\n%2\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n:2\r\n*2\r\n:2039123\r\n:9543892\r\n";
+        let result = from_string(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::WithAttributes {
+            attributes: Box::new(Value::Map(vec![(
+                Value::String("key-popularity".to_string()),
+                Value::Map(vec![
+                    (Value::BulkString(b"a".to_vec()), Value::Integer(1)),
+                    (Value::BulkString(b"b".to_vec()), Value::Integer(2)),
+                ]),
+            )])),
+            value: Box::new(Value::Array(vec![
+                Value::Integer(2039123),
+                Value::Integer(9543892),
+            ])),
+        };
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_resumes_after_timeout() {
+        let reader = MockReader::new(vec![
+            Ok(b"*2\r\n$4\r\nEC".to_vec()),
+            Err(io::Error::new(io::ErrorKind::TimedOut, "read timed out")),
+            Ok(b"HO\r\n$3\r\nhey\r\n".to_vec()),
+        ]);
+        let mut d = Deserializer::new(reader);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::Timeout(_))),
+            "Expected timeout. Found: {:?}",
+            result
+        );
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::Array(vec![
+            Value::BulkString(b"ECHO".to_vec()),
+            Value::BulkString(b"hey".to_vec()),
+        ]);
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_events_drops_consumed_bytes() {
+        #[derive(Default)]
+        struct Count(usize);
+
+        impl ValueSink for Count {
+            fn on_bulk(&mut self, _b: &[u8]) {
+                self.0 += 1;
+            }
+        }
+
+        let body = "x".repeat(10_000);
+        let reader = MockReader::new(vec![
+            Ok(format!("*2\r\n$10000\r\n{}\r\n$3\r\nhe", body).into_bytes()),
+            Err(io::Error::new(io::ErrorKind::TimedOut, "read timed out")),
+            Ok(b"y\r\n:1\r\n".to_vec()),
+        ]);
+        let mut d = Deserializer::new(reader).with_buffer_capacity(64);
+        let mut sink = Count::default();
+        let result = d.parse_events(&mut sink);
+        assert!(
+            matches!(result, Err(Error::Timeout(_))),
+            "Expected timeout. Found: {:?}",
+            result
+        );
+        assert_eq!(sink.0, 1);
+        assert_eq!(d.bytes_consumed(), 10_000 + 20);
+        // The bytes of the frame weren't kept around.
+        assert!(d.pending.capacity() <= 64, "{}", d.pending.capacity());
+        let result = d.recover_next();
+        assert!(result.is_some(), "Expected a value after recovering");
+        assert_eq!(result.unwrap().unwrap(), Value::Integer(1));
+    }
+
+    #[test]
+    fn parse_would_block_is_timeout() {
+        let reader = MockReader::new(vec![Err(io::Error::from(io::ErrorKind::WouldBlock))]);
+        let result = Deserializer::new(reader).parse();
+        assert!(
+            matches!(result, Err(Error::Timeout(_))),
+            "Expected timeout. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_bulk_string_missing_length_ending() {
+        let result = from_string("$4ECHO\r\n");
+        match result {
+            Err(Error::InvalidValue(msg)) => {
+                assert!(
+                    msg.starts_with("Expected CRLF after bulk length"),
+                    "{}",
+                    msg
+                )
+            }
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn parse_stray_line_terminator() {
+        let result = from_string("\r\n");
+        match result {
+            Err(Error::InvalidValue(msg)) => {
+                assert_eq!(msg, "Expected a type prefix, found line terminator")
+            }
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn peek_type_does_not_consume() {
+        let mut d = Deserializer::new("-ERR unknown command\r\n:1\r\n".as_bytes());
+        let result = d.peek_type();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), ValueType::Error);
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(
+            result.unwrap(),
+            Value::Error("ERR unknown command".to_string())
+        );
+        assert_eq!(d.peek_type().unwrap(), ValueType::Integer);
+        assert_eq!(d.parse().unwrap(), Value::Integer(1));
+    }
+
+    #[test]
+    fn parse_expecting() {
+        let mut d = Deserializer::new("$3\r\nfoo\r\n:1\r\n-ERR\r\n".as_bytes());
+        let allowed = [ValueType::Integer, ValueType::Error];
+        match d.parse_expecting(&allowed) {
+            Err(Error::InvalidValue(msg)) => {
+                assert_eq!(msg, "Expected one of [Integer, Error], found BulkString")
+            }
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+        assert_eq!(d.parse_expecting(&allowed).unwrap(), Value::Integer(1));
+        assert_eq!(
+            d.parse_expecting(&allowed).unwrap(),
+            Value::Error("ERR".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_empty_bulk_string() {
+        let mut d = Deserializer::new("$0\r\n\r\n:1\r\n".as_bytes());
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::BulkString(vec![]));
+        // Exactly the trailing CRLF was consumed, the next frame is intact.
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Integer(1));
+    }
+
+    #[test]
+    fn parse_unknown_type() {
+        let data = "*2\r\n@made up\r\n:1\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_unknown_types(true);
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::Array(vec![
+            Value::Unknown {
+                prefix: b'@',
+                raw_line: b"made up".to_vec(),
+            },
+            Value::Integer(1),
+        ]);
+        assert_eq!(result, correct);
+
+        let result = from_string(data);
+        assert!(
+            result.is_err(),
+            "Unknown types should be rejected by default. Found: {:?}",
+            result.unwrap()
+        );
+    }
+
+    #[test]
+    fn into_inner_continues_after_frame() {
+        let mut d = Deserializer::new(":1\r\n+OK\r\n".as_bytes());
+        assert_eq!(d.parse().unwrap(), Value::Integer(1));
+        let mut stream = d.into_inner();
+        let mut buf = [0; 1];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[0], b'+');
+    }
+
+    #[test]
+    fn into_inner_with_buffer_returns_peeked_byte() {
+        let mut d = Deserializer::new(":1\r\n+OK\r\n".as_bytes());
+        assert_eq!(d.parse().unwrap(), Value::Integer(1));
+        assert_eq!(d.peek_type().unwrap(), ValueType::String);
+        let (stream, buffer) = d.into_inner_with_buffer();
+        assert_eq!(buffer, b"+");
+        assert_eq!(stream, b"OK\r\n");
+    }
+
+    #[test]
+    fn parse_string_over_length_limit() {
+        let data = format!("+{}", "A".repeat(1000));
+        let mut d = Deserializer::new(data.as_bytes()).with_max_length(64);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::LimitExceeded(_))),
+            "Expected limit to be exceeded. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_integer_over_length_limit() {
+        let data = format!(":{}\r\n", "1".repeat(100));
+        let mut d = Deserializer::new(data.as_bytes()).with_max_length(64);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::LimitExceeded(_))),
+            "Expected limit to be exceeded. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_bulk_string_over_length_limit() {
+        let mut d = Deserializer::new("$1000\r\n".as_bytes()).with_max_length(64);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::LimitExceeded(_))),
+            "Expected limit to be exceeded. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_escaped() {
+        let result = from_escaped("*1\\r\\n:5\\r\\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        assert_eq!(result, Value::Array(vec![Value::Integer(5)]));
+    }
+
+    #[test]
+    fn parse_escaped_hex() {
+        let result = from_escaped("$3\\r\\n\\x00\\xffa\\r\\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        assert_eq!(result, Value::BulkString(vec![0x00, 0xff, b'a']));
+    }
+
+    #[test]
+    fn parse_escaped_unknown_escape() {
+        let data = "+OK\\q\\r\\n";
+        let result = from_escaped(data);
+        assert!(
+            result.is_err(),
+            "String {} should raise an error. Found: {:?}",
+            data,
+            result.unwrap()
+        );
+    }
+
+    fn assert_invalid_value(data: &str, message: &str) {
+        match from_string(data) {
+            Err(Error::InvalidValue(msg)) => assert_eq!(msg, message),
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn parse_invalid_lengths() {
+        assert_invalid_value("$-2\r\n", "Invalid bulk length `-2`");
+        assert_invalid_value("$\r\n", "Invalid bulk length ``");
+        assert_invalid_value("*-5\r\n", "Invalid array length `-5`");
+        assert_invalid_value("*1x\r\n", "Expected CRLF after array length, found `x`");
+        assert_invalid_value("%-1\r\n", "Invalid map length `-1`");
+    }
+
+    #[test]
+    fn parse_bulk_error() {
+        let result = from_string("!21\r\nSYNTAX invalid syntax\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Value::BulkError(b"SYNTAX invalid syntax".to_vec());
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn parse_huge_declared_array() {
+        let result = from_string("*2000000000\r\n:1\r\n");
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+
+        let mut d = Deserializer::new("*2000000000\r\n:1\r\n".as_bytes()).with_max_elements(1024);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::LimitExceeded(_))),
+            "Expected limit to be exceeded. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_push_frame() {
+        let data = ">2\r\n$7\r\nmessage\r\n$5\r\nhello\r\n*1\r\n:1\r\n";
+        let mut d = Deserializer::new(data.as_bytes());
+        let result = d.parse_frame();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let correct = Frame::Push(vec![
+            Value::BulkString(b"message".to_vec()),
+            Value::BulkString(b"hello".to_vec()),
+        ]);
+        assert_eq!(result.unwrap(), correct);
+
+        let result = d.parse_frame();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let correct = Frame::Value(Value::Array(vec![Value::Integer(1)]));
+        assert_eq!(result.unwrap(), correct);
+    }
+
+    #[test]
+    fn parse_attribute_frame() {
+        let data = "|1\r\n+ttl\r\n:10\r\n:1\r\n";
+        let result = Deserializer::new(data.as_bytes()).parse_frame();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = Frame::Attribute {
+            attributes: vec![(Value::String("ttl".to_string()), Value::Integer(10))],
+            frame: Box::new(Frame::Value(Value::Integer(1))),
+        };
+        assert_eq!(result, correct);
+        assert_eq!(result.into_value(), from_string(data).unwrap());
+    }
+
+    #[test]
+    fn from_bytes_all_capped() {
+        let data = b"+OK\r\n:1\r\n:2\r\n$3\r\nhey\r\n:3\r\n";
+        let result = from_bytes_all(data, 3);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let (values, rest) = result.unwrap();
+        let correct = vec![
+            Value::String("OK".to_string()),
+            Value::Integer(1),
+            Value::Integer(2),
+        ];
+        assert_eq!(values, correct);
+        assert_eq!(rest, b"$3\r\nhey\r\n:3\r\n");
+
+        let (values, rest) = from_bytes_all(rest, 3).unwrap();
+        assert_eq!(
+            values,
+            vec![Value::BulkString(b"hey".to_vec()), Value::Integer(3)]
+        );
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn detect_protocol() {
+        assert_eq!(
+            super::detect_protocol(b"%1\r\n+a\r\n:1\r\n"),
+            Some(ProtocolVersion::Resp3)
+        );
+        assert_eq!(
+            super::detect_protocol(b"_\r\n"),
+            Some(ProtocolVersion::Resp3)
+        );
+        assert_eq!(
+            super::detect_protocol(b"*1\r\n:1\r\n"),
+            Some(ProtocolVersion::Resp2)
+        );
+        assert_eq!(super::detect_protocol(b""), None);
+    }
+
+    #[test]
+    fn iterate_values() {
+        let d = Deserializer::new("+OK\r\n:1\r\n".as_bytes());
+        let result: Vec<Value> = d.map(|v| v.unwrap()).collect();
+        let correct = vec![Value::String("OK".to_string()), Value::Integer(1)];
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn next_value_until_eof() {
+        let mut d = Deserializer::new("+OK\r\n:1\r\n".as_bytes());
+        assert_eq!(
+            d.next_value().unwrap(),
+            Some(Value::String("OK".to_string()))
+        );
+        assert_eq!(d.next_value().unwrap(), Some(Value::Integer(1)));
+        assert_eq!(d.next_value().unwrap(), None);
+
+        let mut d = Deserializer::new("+OK\r\n*2\r\n:1\r\n".as_bytes());
+        assert!(d.next_value().unwrap().is_some());
+        let result = d.next_value();
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream error. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn for_each_value_until_error() {
+        let data = "+OK\r\n:1\r\n-ERR oops\r\n:2\r\n";
+        let mut d = Deserializer::new(data.as_bytes());
+        let mut seen = vec![];
+        let result = d.for_each_value(|value| {
+            let error = value.is_error();
+            seen.push(value);
+            if error {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen[2], Value::Error("ERR oops".to_string()));
+        assert_eq!(d.parse().unwrap(), Value::Integer(2));
+
+        let mut count = 0;
+        let result = Deserializer::new("+OK\r\n:1\r\n".as_bytes()).for_each_value(|_| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn recover_after_corrupt_frame() {
+        let data = "+OK\r\n*2\r\n:1\r\n?garbage\r\n+FINE\r\n";
+        // With a larger buffer the skipped bytes are already read ahead.
+        for capacity in [1, 64] {
+            let mut d = Deserializer::new(data.as_bytes()).with_buffer_capacity(capacity);
+            assert_eq!(d.next().unwrap().unwrap(), Value::String("OK".to_string()));
+            let result = d.next().unwrap();
+            assert!(
+                result.is_err(),
+                "Corrupt frame should raise an error. Found: {:?}",
+                result.unwrap()
+            );
+            let result = d.recover_next();
+            assert!(result.is_some(), "Expected a value after recovering");
+            let result = result.unwrap();
+            assert!(result.is_ok(), "{:?}", result.err().unwrap());
+            assert_eq!(result.unwrap(), Value::String("FINE".to_string()));
+            assert_eq!(d.bytes_consumed(), data.len() as u64);
+            assert!(d.recover_next().is_none());
+        }
+    }
+
+    #[test]
+    fn collect_stats() {
+        let data = "+OK\r\n*2\r\n$4\r\nECHO\r\n*1\r\n$3\r\nhey\r\n:1\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_stats(true);
+        for result in d.by_ref() {
+            assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        }
+        let result = d.stats().unwrap();
+        let correct = Stats {
+            frames: 3,
+            bytes: data.len() as u64,
+            max_depth: 3,
+            largest_bulk: 4,
+        };
+        assert_eq!(result, &correct);
+    }
+
+    #[test]
+    fn bytes_consumed() {
+        let data = "*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n";
+        let mut d = Deserializer::new(data.as_bytes());
+        assert_eq!(d.bytes_consumed(), 0);
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(d.bytes_consumed(), data.len() as u64);
+
+        let data = format!("{}:1\r\n", data);
+        let mut d = Deserializer::new(data.as_bytes()).with_buffer_capacity(1024);
+        d.parse().unwrap();
+        assert_eq!(d.bytes_consumed(), data.len() as u64 - 4);
+    }
+
+    #[test]
+    fn stats_disabled_by_default() {
+        let mut d = Deserializer::new(":1\r\n".as_bytes());
+        d.parse().unwrap();
+        assert_eq!(d.stats(), None);
+    }
+
+    #[test]
+    fn parse_double() {
+        let result = from_string(",2.5\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Double(2.5));
+        let result = from_string(",-inf\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Double(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn parse_empty_lines() {
+        let result = from_string("+\r\n");
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::String(String::new()));
+        let result = Deserializer::new("-\r\n".as_bytes()).parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::Error(String::new()));
+
+        let results = [
+            from_string(":\r\n"),
+            Deserializer::new(":\r\n".as_bytes()).parse(),
+        ];
+        for result in results {
+            match result {
+                Err(Error::InvalidValue(msg)) => assert_eq!(msg, "Empty integer"),
+                r => panic!("Expected invalid value error. Found: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_infinite_integer() {
+        for data in [":inf\r\n", ":-inf\r\n"] {
+            match from_string(data) {
+                Err(Error::InvalidValue(msg)) => {
+                    assert_eq!(msg, "Infinity is only valid for RESP3 doubles")
+                }
+                r => panic!("Expected invalid value error. Found: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_big_number() {
+        let data = "(3492890328409238509324850943850943825024385\r\n";
+        let result = from_string(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let correct = Value::BigNumber("3492890328409238509324850943850943825024385".to_string());
+        assert_eq!(result.unwrap(), correct);
+
+        let data = "(12a\r\n";
+        let result = from_string(data);
+        assert!(
+            result.is_err(),
+            "String {} shouldnt parse to big number. Found: {:?}",
+            data,
+            result.unwrap()
+        );
+    }
+
+    #[test]
+    fn unique_map_keys() {
+        let data = "%3\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n:2\r\n$1\r\na\r\n:3\r\n";
+        let result = from_string(data);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+
+        let mut d = Deserializer::new(data.as_bytes()).with_unique_map_keys(true);
+        match d.parse() {
+            Err(Error::InvalidValue(msg)) => assert_eq!(msg, "Duplicate map key \"a\""),
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+
+        let data = "%2\r\n:1\r\n:1\r\n$1\r\n1\r\n:1\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_unique_map_keys(true);
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+    }
+
+    #[test]
+    fn register_type() {
+        let hex = |line: &[u8]| {
+            let line = std::str::from_utf8(line).unwrap_or_default();
+            i64::from_str_radix(line, 16)
+                .map(Value::Integer)
+                .map_err(|_| Error::InvalidValue(format!("Invalid hex `{}`", line)))
+        };
+        let data = "@ff\r\n*2\r\n@10\r\n+OK\r\n@zz\r\n";
+        let mut d = Deserializer::new(data.as_bytes())
+            .register_type(b'@', hex)
+            .register_type(b'+', |_| Ok(Value::Integer(0)));
+        assert_eq!(d.peek_type().unwrap(), ValueType::Unknown);
+        assert_eq!(d.parse().unwrap(), Value::Integer(255));
+        let correct = Value::Array(vec![Value::Integer(16), Value::String("OK".to_string())]);
+        assert_eq!(d.parse().unwrap(), correct);
+        match d.parse() {
+            Err(Error::InvalidValue(msg)) => assert_eq!(msg, "Invalid hex `zz`"),
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn buffer_capacity() {
+        let body = "x".repeat(100_000);
+        let data = format!("${}\r\n{}\r\n:1\r\n+OK", body.len(), body);
+        let mut d = Deserializer::new(data.as_bytes()).with_buffer_capacity(8192);
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Value::BulkString(body.into_bytes()));
+        assert_eq!(d.parse().unwrap(), Value::Integer(1));
+        // Read ahead, but not consumed.
+        let (_, buffer) = d.into_inner_with_buffer();
+        assert_eq!(buffer, b"+OK");
+    }
+
+    #[test]
+    fn max_frame_bytes() {
+        let data = "*2\r\n*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n*1\r\n$3\r\nbaz\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_max_frame_bytes(data.len());
+        let result = d.parse();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+
+        // Fails on `baz`, well after the nested array was parsed.
+        let mut d = Deserializer::new(data.as_bytes()).with_max_frame_bytes(data.len() - 1);
+        match d.parse() {
+            Err(Error::LimitExceeded(msg)) => assert_eq!(
+                msg,
+                format!("Frame size exceeds the limit of {} bytes", data.len() - 1)
+            ),
+            r => panic!("Expected limit exceeded error. Found: {:?}", r),
+        }
+
+        let mut d =
+            Deserializer::new("*2\r\n*1\r\n:1\r\n:2\r\n".as_bytes()).with_max_frame_bytes(10);
+        let result = d.parse();
+        assert!(
+            matches!(result, Err(Error::LimitExceeded(_))),
+            "Expected limit exceeded error. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn bulk_capacity_hint() {
+        let data = "$5\r\nhello\r\n";
+        let mut d = Deserializer::new(data.as_bytes()).with_bulk_capacity_hint(512);
+        match d.parse() {
+            Ok(Value::BulkString(body)) => {
+                assert_eq!(body, b"hello");
+                // Allocated once from the declared length, never regrown.
+                assert_eq!(body.capacity(), 5);
+            }
+            r => panic!("Expected bulk string. Found: {:?}", r),
+        }
+
+        // Past the hint, the body starts from the hint and grows as it's read
+        // instead of trusting the declared length.
+        let data = format!("$1000\r\n{}\r\n", "x".repeat(1000));
+        let mut d = Deserializer::new(data.as_bytes()).with_bulk_capacity_hint(512);
+        match d.parse() {
+            Ok(Value::BulkString(body)) => {
+                assert_eq!(body, vec![b'x'; 1000]);
+                assert_eq!(body.capacity(), 1024);
+            }
+            r => panic!("Expected bulk string. Found: {:?}", r),
+        }
+        let mut d = Deserializer::new(data.as_bytes());
+        match d.parse() {
+            Ok(Value::BulkString(body)) => assert_eq!(body.capacity(), 1000),
+            r => panic!("Expected bulk string. Found: {:?}", r),
+        }
+    }
 }
@@ -0,0 +1,28 @@
+use crate::value::Value;
+
+/// A top-level unit of the protocol. Separates out-of-band data, pushes and
+/// attributes, from the replies themselves.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Frame {
+    Value(Value),
+    Push(Vec<Value>), // https://redis.io/docs/reference/protocol-spec/#pushes
+    Attribute {
+        attributes: Vec<(Value, Value)>,
+        frame: Box<Frame>,
+    }, // https://redis.io/docs/reference/protocol-spec/#attributes
+}
+
+impl Frame {
+    /// Flattens the frame into a `Value`: pushes become arrays and attributes
+    /// become `Value::WithAttributes`.
+    pub fn into_value(self) -> Value {
+        match self {
+            Frame::Value(value) => value,
+            Frame::Push(items) => Value::Array(items),
+            Frame::Attribute { attributes, frame } => Value::WithAttributes {
+                attributes: Box::new(Value::Map(attributes)),
+                value: Box::new(frame.into_value()),
+            },
+        }
+    }
+}
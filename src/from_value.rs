@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::deserializer::{Error, Result};
+use crate::value::Value;
+
+/// Conversion from a parsed reply into a Rust type.
+pub trait FromRespValue: Sized {
+    fn from_value(value: Value) -> Result<Self>;
+}
+
+pub(crate) fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "simple string",
+        Value::Error(_) => "error",
+        Value::Integer(_) => "integer",
+        Value::Double(_) | Value::RawDouble { .. } => "double",
+        Value::BigNumber(_) => "big number",
+        Value::BulkString(_) => "bulk string",
+        Value::Array(_) => "array",
+        Value::Null(_) => "null",
+        Value::BulkError(_) => "bulk error",
+        Value::Verbatim { .. } => "verbatim string",
+        Value::Map(_) => "map",
+        Value::Set(_) => "set",
+        Value::WithAttributes { .. } => "value with attributes",
+        Value::Unknown { .. } => "unknown type",
+    }
+}
+
+pub(crate) fn unexpected<T>(expected: &str, value: &Value) -> Result<T> {
+    Err(Error::InvalidValue(format!(
+        "Expected {}, found {}",
+        expected,
+        kind(value)
+    )))
+}
+
+impl FromRespValue for Value {
+    fn from_value(value: Value) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+impl FromRespValue for String {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(s),
+            Value::BulkString(b) | Value::Verbatim { data: b, .. } => String::from_utf8(b)
+                .map_err(|_| Error::InvalidValue("Non UTF-8 bulk string".to_string())),
+            v => unexpected("string", &v),
+        }
+    }
+}
+
+impl FromRespValue for i64 {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Integer(i) => Ok(i),
+            // Plenty of commands, e.g. `CONFIG GET`, send numbers as strings.
+            Value::String(_) | Value::BulkString(_) => {
+                let s = String::from_value(value)?;
+                s.parse::<i64>()
+                    .map_err(|_| Error::InvalidValue(format!("Can't parse `{}` as integer", s)))
+            }
+            v => unexpected("integer", &v),
+        }
+    }
+}
+
+impl FromRespValue for bool {
+    fn from_value(value: Value) -> Result<Self> {
+        match i64::from_value(value)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            i => Err(Error::InvalidValue(format!(
+                "Expected 0 or 1 for boolean, found {}",
+                i
+            ))),
+        }
+    }
+}
+
+impl<T: FromRespValue> FromRespValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Null(_) => Ok(None),
+            v => Ok(Some(T::from_value(v)?)),
+        }
+    }
+}
+
+impl<T: FromRespValue> FromRespValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Array(items) | Value::Set(items) => {
+                items.into_iter().map(T::from_value).collect()
+            }
+            v => unexpected("array", &v),
+        }
+    }
+}
+
+/// Accepts RESP3 maps as well as the flat key/value arrays RESP2 uses for
+/// replies such as `CONFIG GET` or `HGETALL`.
+impl<K: FromRespValue + Eq + Hash, V: FromRespValue> FromRespValue for HashMap<K, V> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Map(entries) => entries
+                .into_iter()
+                .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+                .collect(),
+            Value::Array(items) if items.len() % 2 == 0 => {
+                let mut result = HashMap::with_capacity(items.len() / 2);
+                let mut items = items.into_iter();
+                while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                    result.insert(K::from_value(k)?, V::from_value(v)?);
+                }
+                Ok(result)
+            }
+            Value::Array(_) => Err(Error::InvalidValue(
+                "Expected an even number of elements for key/value array".to_string(),
+            )),
+            v => unexpected("map", &v),
+        }
+    }
+}
+
+macro_rules! tuple_from_value {
+    ($length:expr; $($name:ident),+) => {
+        impl<$($name: FromRespValue),+> FromRespValue for ($($name,)+) {
+            fn from_value(value: Value) -> Result<Self> {
+                match value {
+                    Value::Array(items) if items.len() == $length => {
+                        let mut items = items.into_iter();
+                        Ok(($($name::from_value(items.next().unwrap())?,)+))
+                    }
+                    Value::Array(items) => Err(Error::InvalidValue(format!(
+                        "Expected array of {} elements, found {}",
+                        $length,
+                        items.len()
+                    ))),
+                    v => unexpected("array", &v),
+                }
+            }
+        }
+    };
+}
+
+tuple_from_value!(1; A);
+tuple_from_value!(2; A, B);
+tuple_from_value!(3; A, B, C);
+tuple_from_value!(4; A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deserializer::{from_bytes, from_string};
+    use crate::value::NullKind;
+
+    #[test]
+    fn vec_of_strings() {
+        let value = from_string("*2\r\n$4\r\nECHO\r\n+hey\r\n").unwrap();
+        let result = Vec::<String>::from_value(value);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = vec!["ECHO".to_string(), "hey".to_string()];
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn tuple() {
+        let value = from_string("*2\r\n:42\r\n$3\r\nhey\r\n").unwrap();
+        let result = <(i64, String)>::from_value(value);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = (42, "hey".to_string());
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn tuple_wrong_length() {
+        let value = from_string("*1\r\n:42\r\n").unwrap();
+        let result = <(i64, String)>::from_value(value);
+        assert!(
+            result.is_err(),
+            "Array of one element shouldn't convert to a pair. Found: {:?}",
+            result.unwrap()
+        );
+    }
+
+    #[test]
+    fn integer_from_bulk_string() {
+        let value = Value::BulkString(b"100".to_vec());
+        let result = i64::from_value(value);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), 100);
+    }
+
+    #[test]
+    fn hashmap_from_flat_array() {
+        let value =
+            from_string("*4\r\n$7\r\nmaxconn\r\n$2\r\n10\r\n$4\r\nport\r\n$4\r\n6379\r\n").unwrap();
+        let result = HashMap::<String, i64>::from_value(value);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = HashMap::from([("maxconn".to_string(), 10), ("port".to_string(), 6379)]);
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn option() {
+        let result = Option::<String>::from_value(Value::Null(NullKind::Resp3));
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), None);
+    }
+
+    #[test]
+    fn option_from_reply() {
+        // `GET` on a missing key, then on an existing one.
+        let result = Option::<String>::from_value(from_bytes(b"$-1\r\n").unwrap());
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), None);
+        let result = Option::<String>::from_value(from_bytes(b"$3\r\nbar\r\n").unwrap());
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), Some("bar".to_string()));
+
+        let result = Option::<i64>::from_value(from_bytes(b"$3\r\nbar\r\n").unwrap());
+        assert!(
+            matches!(result, Err(Error::InvalidValue(_))),
+            "Expected invalid value error. Found: {:?}",
+            result
+        );
+    }
+}
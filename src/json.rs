@@ -0,0 +1,170 @@
+use serde_json::{Map, Value as JsonValue};
+
+use crate::value::Value;
+
+/// How `Value::to_json_value` writes bulk bodies that aren't valid UTF-8.
+/// Valid UTF-8 is always written as a plain string.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum BulkEncoding {
+    /// Invalid sequences are replaced with U+FFFD.
+    #[default]
+    Utf8Lossy,
+    /// Standard base64 with padding.
+    Base64,
+    /// Lowercase hex, two digits per byte.
+    HexString,
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn bytes_to_string(data: &[u8], encoding: BulkEncoding) -> String {
+    match (std::str::from_utf8(data), encoding) {
+        (Ok(s), _) => s.to_string(),
+        (Err(_), BulkEncoding::Utf8Lossy) => String::from_utf8_lossy(data).to_string(),
+        (Err(_), BulkEncoding::Base64) => base64(data),
+        (Err(_), BulkEncoding::HexString) => data.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+// Map keys have to be strings in JSON.
+fn key_to_string(key: &Value, encoding: BulkEncoding) -> Option<String> {
+    match key {
+        Value::String(s) => Some(s.clone()),
+        Value::BulkString(b) => Some(bytes_to_string(b, encoding)),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::BigNumber(n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+fn error(message: String) -> JsonValue {
+    let mut result = Map::new();
+    result.insert("error".to_string(), JsonValue::String(message));
+    JsonValue::Object(result)
+}
+
+impl Value {
+    /// Converts the value to JSON. Bulk bodies that aren't valid UTF-8 are
+    /// written as strings according to `encoding`.
+    ///
+    /// Errors become `{"error": "..."}`, big numbers and non-finite doubles
+    /// become strings and attributes are dropped. Maps become objects when
+    /// every key is a string or a number, arrays of `[key, value]` pairs
+    /// otherwise.
+    pub fn to_json_value(&self, encoding: BulkEncoding) -> JsonValue {
+        match self {
+            Value::String(s) => JsonValue::String(s.clone()),
+            Value::Error(e) => error(e.clone()),
+            Value::BulkError(e) => error(bytes_to_string(e, encoding)),
+            Value::Integer(i) => JsonValue::from(*i),
+            Value::Double(d) | Value::RawDouble { value: d, .. } => {
+                serde_json::Number::from_f64(*d)
+                    .map_or_else(|| JsonValue::String(d.to_string()), JsonValue::Number)
+            }
+            Value::BigNumber(n) => JsonValue::String(n.clone()),
+            Value::BulkString(b) => JsonValue::String(bytes_to_string(b, encoding)),
+            Value::Verbatim { data, .. } => JsonValue::String(bytes_to_string(data, encoding)),
+            Value::Array(items) | Value::Set(items) => {
+                JsonValue::Array(items.iter().map(|v| v.to_json_value(encoding)).collect())
+            }
+            Value::Null(_) => JsonValue::Null,
+            Value::Map(entries) => {
+                let keys: Option<Vec<String>> = entries
+                    .iter()
+                    .map(|(k, _)| key_to_string(k, encoding))
+                    .collect();
+                match keys {
+                    Some(keys) => JsonValue::Object(
+                        keys.into_iter()
+                            .zip(entries.iter().map(|(_, v)| v.to_json_value(encoding)))
+                            .collect(),
+                    ),
+                    None => JsonValue::Array(
+                        entries
+                            .iter()
+                            .map(|(k, v)| {
+                                JsonValue::Array(vec![
+                                    k.to_json_value(encoding),
+                                    v.to_json_value(encoding),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                }
+            }
+            Value::WithAttributes { value, .. } => value.to_json_value(encoding),
+            Value::Unknown { raw_line, .. } => {
+                JsonValue::String(bytes_to_string(raw_line, encoding))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn binary() -> Value {
+        Value::BulkString(vec![b'h', b'i', 0xff, 0x00])
+    }
+
+    #[test]
+    fn utf8_lossy() {
+        let result = binary().to_json_value(BulkEncoding::Utf8Lossy);
+        assert_eq!(result, json!("hi\u{fffd}\u{0}"));
+    }
+
+    #[test]
+    fn base64() {
+        let result = binary().to_json_value(BulkEncoding::Base64);
+        assert_eq!(result, json!("aGn/AA=="));
+        let result = Value::BulkString(vec![0xff]).to_json_value(BulkEncoding::Base64);
+        assert_eq!(result, json!("/w=="));
+        let result = Value::BulkString(vec![0xfb, 0xef, 0xbe]).to_json_value(BulkEncoding::Base64);
+        assert_eq!(result, json!("++++"));
+    }
+
+    #[test]
+    fn hex_string() {
+        let result = binary().to_json_value(BulkEncoding::HexString);
+        assert_eq!(result, json!("6869ff00"));
+        // Valid UTF-8 stays readable whatever the policy.
+        let result = Value::BulkString(b"hey".to_vec()).to_json_value(BulkEncoding::HexString);
+        assert_eq!(result, json!("hey"));
+    }
+
+    #[test]
+    fn nested() {
+        let value = Value::Map(vec![
+            (
+                Value::BulkString(b"a".to_vec()),
+                Value::Array(vec![Value::Integer(1), Value::Null(Default::default())]),
+            ),
+            (Value::Integer(2), Value::Error("ERR".to_string())),
+        ]);
+        let result = value.to_json_value(BulkEncoding::default());
+        assert_eq!(result, json!({"a": [1, null], "2": {"error": "ERR"}}));
+    }
+}
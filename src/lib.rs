@@ -1,4 +1,17 @@
+pub mod borrowed;
+pub mod cli;
+pub mod deadline;
 pub mod deserializer;
+pub mod frame;
+pub mod from_value;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod observer;
+pub mod serializer;
+pub mod shape;
+pub mod shared;
+pub mod sink;
+pub mod value;
 
 // TODO: make integration tests
 #[cfg(test)]
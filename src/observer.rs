@@ -0,0 +1,31 @@
+use std::fmt;
+
+use crate::deserializer::Error;
+
+/// Hooks called by a `Deserializer` created `with_observer`, e.g. to trace
+/// framing problems through `log` or `tracing`.
+///
+/// Every method does nothing by default. Within a frame, each value reports
+/// its start and, once fully parsed, its end; nested values are reported
+/// between the start and end of the aggregate holding them.
+#[allow(unused_variables)]
+pub trait ParseObserver {
+    /// A new frame is about to be parsed.
+    fn on_frame_start(&mut self) {}
+    /// The frame was parsed from `bytes` bytes of input.
+    fn on_frame_end(&mut self, bytes: usize) {}
+    /// A value with type prefix `prefix` starts, `depth` being 1 for the
+    /// frame itself.
+    fn on_value_start(&mut self, prefix: u8, depth: usize) {}
+    fn on_value_end(&mut self, depth: usize) {}
+    /// Parsing the frame failed.
+    fn on_error(&mut self, error: &Error) {}
+}
+
+pub(crate) struct Observer(pub(crate) Box<dyn ParseObserver + Send>);
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
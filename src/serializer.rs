@@ -0,0 +1,432 @@
+use std::io::Write;
+
+use crate::deserializer::{
+    check_big_number, check_verbatim_format, double_from_str, Error, Result,
+};
+use crate::value::{NullKind, Value};
+
+/// Protocol version to target when serializing, deciding how values that
+/// are encoded differently between versions, such as null, are written.
+/// Without one, values are written back in the encoding they were read from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ProtocolVersion {
+    /// RESP3 nulls are written as a null bulk string, `$-1`.
+    #[default]
+    Resp2,
+    /// All nulls are written as `_`.
+    Resp3,
+}
+
+fn write_map<W: Write>(
+    writer: &mut W,
+    prefix: u8,
+    entries: &[(Value, Value)],
+    version: Option<ProtocolVersion>,
+) -> Result<()> {
+    write!(writer, "{}{}\r\n", prefix as char, entries.len()).map_err(Error::from_io)?;
+    for (key, value) in entries {
+        write_value(writer, key, version)?;
+        write_value(writer, value, version)?;
+    }
+    Ok(())
+}
+
+fn write_elements<W: Write>(
+    writer: &mut W,
+    prefix: u8,
+    items: &[Value],
+    version: Option<ProtocolVersion>,
+) -> Result<()> {
+    write!(writer, "{}{}\r\n", prefix as char, items.len()).map_err(Error::from_io)?;
+    for item in items {
+        write_value(writer, item, version)?;
+    }
+    Ok(())
+}
+
+// Simple strings and errors are terminated by the first CR or LF, so they
+// can't contain either.
+fn write_line<W: Write>(writer: &mut W, prefix: u8, line: &str) -> Result<()> {
+    if line.contains(['\r', '\n']) {
+        return Err(Error::InvalidValue(
+            "Simple strings can't contain \\r or \\n".to_string(),
+        ));
+    }
+    write!(writer, "{}{}\r\n", prefix as char, line).map_err(Error::from_io)
+}
+
+fn write_blob<W: Write>(writer: &mut W, prefix: u8, data: &[u8]) -> Result<()> {
+    write!(writer, "{}{}\r\n", prefix as char, data.len()).map_err(Error::from_io)?;
+    writer.write_all(data).map_err(Error::from_io)?;
+    writer.write_all(b"\r\n").map_err(Error::from_io)
+}
+
+fn write_double<W: Write>(writer: &mut W, value: f64) -> Result<()> {
+    // Rust spells these `inf` and `NaN`, RESP wants `inf` and `nan`.
+    if value.is_nan() {
+        writer.write_all(b",nan\r\n").map_err(Error::from_io)
+    } else {
+        write!(writer, ",{}\r\n", value).map_err(Error::from_io)
+    }
+}
+
+fn write_value<W: Write>(
+    writer: &mut W,
+    value: &Value,
+    version: Option<ProtocolVersion>,
+) -> Result<()> {
+    match value {
+        Value::String(s) => write_line(writer, b'+', s),
+        Value::Error(e) => write_line(writer, b'-', e),
+        Value::Integer(i) => write!(writer, ":{}\r\n", i).map_err(Error::from_io),
+        Value::Double(d) => write_double(writer, *d),
+        Value::RawDouble { raw, .. } => {
+            double_from_str(raw)?;
+            write!(writer, ",{}\r\n", raw).map_err(Error::from_io)
+        }
+        Value::BigNumber(n) => {
+            check_big_number(n)?;
+            write!(writer, "({}\r\n", n).map_err(Error::from_io)
+        }
+        Value::BulkString(b) => write_blob(writer, b'$', b),
+        Value::BulkError(b) => write_blob(writer, b'!', b),
+        Value::Verbatim { format, data } => {
+            check_verbatim_format(format)?;
+            // The body holds the format, `:` and the data.
+            write!(writer, "={}\r\n{}:", 4 + data.len(), format).map_err(Error::from_io)?;
+            writer.write_all(data).map_err(Error::from_io)?;
+            writer.write_all(b"\r\n").map_err(Error::from_io)
+        }
+        Value::Array(items) => write_elements(writer, b'*', items, version),
+        Value::Set(items) => write_elements(writer, b'~', items, version),
+        Value::Null(kind) => match (version, kind) {
+            (Some(ProtocolVersion::Resp3), _) | (None, NullKind::Resp3) => {
+                writer.write_all(b"_\r\n")
+            }
+            (_, NullKind::Array) => writer.write_all(b"*-1\r\n"),
+            _ => writer.write_all(b"$-1\r\n"),
+        }
+        .map_err(Error::from_io),
+        Value::Map(entries) => write_map(writer, b'%', entries, version),
+        Value::WithAttributes { attributes, value } => {
+            match attributes.as_ref() {
+                Value::Map(entries) => write_map(writer, b'|', entries, version)?,
+                _ => return Err(Error::InvalidValue("Attributes must be a map".to_string())),
+            }
+            write_value(writer, value, version)
+        }
+        Value::Unknown { prefix, raw_line } => {
+            writer.write_all(&[*prefix]).map_err(Error::from_io)?;
+            writer.write_all(raw_line).map_err(Error::from_io)?;
+            writer.write_all(b"\r\n").map_err(Error::from_io)
+        }
+    }
+}
+
+/// Serializes `value` into `writer`, writing nulls in the encoding they were
+/// read from.
+pub fn to_writer<W: Write>(mut writer: W, value: &Value) -> Result<()> {
+    write_value(&mut writer, value, None)
+}
+
+/// Serializes `value` into `writer`, targeting the given protocol version.
+pub fn to_writer_with_version<W: Write>(
+    mut writer: W,
+    value: &Value,
+    version: ProtocolVersion,
+) -> Result<()> {
+    write_value(&mut writer, value, Some(version))
+}
+
+/// Serializes `value` into a new buffer.
+pub fn to_bytes(value: &Value) -> Result<Vec<u8>> {
+    let mut result = vec![];
+    to_writer(&mut result, value)?;
+    Ok(result)
+}
+
+/// Serializes `value` into a new buffer, targeting the given protocol version.
+pub fn to_bytes_with_version(value: &Value, version: ProtocolVersion) -> Result<Vec<u8>> {
+    let mut result = vec![];
+    to_writer_with_version(&mut result, value, version)?;
+    Ok(result)
+}
+
+/// Writes a reply piece by piece, so a large aggregate can be streamed
+/// without building it as a `Value` first.
+///
+/// Aggregate headers only announce a length, writing that many elements
+/// afterwards is up to the caller.
+#[derive(Debug)]
+pub struct ResponseWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ResponseWriter<W> {
+    pub fn new(writer: W) -> ResponseWriter<W> {
+        ResponseWriter { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self, prefix: u8, length: usize) -> Result<()> {
+        write!(self.writer, "{}{}\r\n", prefix as char, length).map_err(Error::from_io)
+    }
+
+    pub fn write_array_header(&mut self, length: usize) -> Result<()> {
+        self.write_header(b'*', length)
+    }
+
+    pub fn write_set_header(&mut self, length: usize) -> Result<()> {
+        self.write_header(b'~', length)
+    }
+
+    /// Announces a map of `length` entries, each to be written as a key
+    /// followed by its value.
+    pub fn write_map_header(&mut self, length: usize) -> Result<()> {
+        self.write_header(b'%', length)
+    }
+
+    pub fn write_simple_string(&mut self, s: &str) -> Result<()> {
+        write_line(&mut self.writer, b'+', s)
+    }
+
+    pub fn write_error(&mut self, e: &str) -> Result<()> {
+        write_line(&mut self.writer, b'-', e)
+    }
+
+    pub fn write_integer(&mut self, i: i64) -> Result<()> {
+        write!(self.writer, ":{}\r\n", i).map_err(Error::from_io)
+    }
+
+    pub fn write_double(&mut self, d: f64) -> Result<()> {
+        write_double(&mut self.writer, d)
+    }
+
+    pub fn write_bulk(&mut self, data: &[u8]) -> Result<()> {
+        write_blob(&mut self.writer, b'$', data)
+    }
+
+    /// Writes a null bulk string, `$-1`.
+    pub fn write_null(&mut self) -> Result<()> {
+        self.writer.write_all(b"$-1\r\n").map_err(Error::from_io)
+    }
+
+    /// Writes a complete value, e.g. a nested element.
+    pub fn write_value(&mut self, value: &Value) -> Result<()> {
+        write_value(&mut self.writer, value, None)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(Error::from_io)
+    }
+}
+
+/// Encodes a command as the array of bulk strings Redis expects from clients.
+pub fn encode_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut result = vec![];
+    encode_command_into(&mut result, args);
+    result
+}
+
+fn encode_command_into(buffer: &mut Vec<u8>, args: &[&[u8]]) {
+    buffer.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        buffer.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buffer.extend_from_slice(arg);
+        buffer.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Encodes several commands back to back, so a pipeline can be sent with a
+/// single write.
+pub fn encode_pipeline(commands: &[&[&[u8]]]) -> Vec<u8> {
+    let mut result = vec![];
+    for args in commands {
+        encode_command_into(&mut result, args);
+    }
+    result
+}
+
+/// Encodes a command in the inline format, `CMD arg1 arg2\r\n`, as typed in
+/// a telnet session. Arguments that are empty or contain whitespace can't be
+/// written that way and are rejected.
+pub fn encode_inline(args: &[&str]) -> Result<String> {
+    if args.is_empty() {
+        return Err(Error::InvalidValue(
+            "Inline command needs at least one argument".to_string(),
+        ));
+    }
+    for arg in args {
+        if arg.is_empty() || arg.contains(|c: char| c.is_ascii_whitespace()) {
+            return Err(Error::InvalidValue(format!(
+                "Argument {:?} can't be sent inline",
+                arg
+            )));
+        }
+    }
+    Ok(format!("{}\r\n", args.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_array() {
+        let value = Value::Array(vec![
+            Value::BulkString(Vec::from("ECHO".as_bytes())),
+            Value::BulkString(Vec::from("hey".as_bytes())),
+        ]);
+        let result = to_bytes(&value);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n";
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn response_writer() {
+        let value = Value::Array(vec![
+            Value::BulkString(Vec::from("ECHO".as_bytes())),
+            Value::BulkString(Vec::from("hey".as_bytes())),
+        ]);
+        let mut w = ResponseWriter::new(vec![]);
+        w.write_array_header(2).unwrap();
+        w.write_bulk(b"ECHO").unwrap();
+        w.write_bulk(b"hey").unwrap();
+        assert_eq!(w.into_inner(), to_bytes(&value).unwrap());
+
+        let value = Value::Map(vec![(
+            Value::String("n".to_string()),
+            Value::Array(vec![Value::Integer(1), Value::Null(NullKind::BulkString)]),
+        )]);
+        let mut w = ResponseWriter::new(vec![]);
+        w.write_map_header(1).unwrap();
+        w.write_simple_string("n").unwrap();
+        w.write_array_header(2).unwrap();
+        w.write_integer(1).unwrap();
+        w.write_null().unwrap();
+        assert_eq!(w.into_inner(), to_bytes(&value).unwrap());
+
+        let mut w = ResponseWriter::new(vec![]);
+        assert!(w.write_error("ERR\r\n").is_err());
+    }
+
+    #[test]
+    fn serialize_scalars() {
+        assert_eq!(
+            to_bytes(&Value::String("OK".to_string())).unwrap(),
+            b"+OK\r\n"
+        );
+        assert_eq!(
+            to_bytes(&Value::Error("ERR".to_string())).unwrap(),
+            b"-ERR\r\n"
+        );
+        assert_eq!(to_bytes(&Value::Integer(-5)).unwrap(), b":-5\r\n");
+        assert_eq!(
+            to_bytes(&Value::Null(NullKind::BulkString)).unwrap(),
+            b"$-1\r\n"
+        );
+    }
+
+    #[test]
+    fn serialize_attributes() {
+        let value = Value::WithAttributes {
+            attributes: Box::new(Value::Map(vec![(
+                Value::String("ttl".to_string()),
+                Value::Integer(3600),
+            )])),
+            value: Box::new(Value::Integer(1)),
+        };
+        let result = to_bytes(&value).unwrap();
+        assert_eq!(result, b"|1\r\n+ttl\r\n:3600\r\n:1\r\n");
+    }
+
+    #[test]
+    fn serialize_unknown() {
+        let value = Value::Unknown {
+            prefix: b'@',
+            raw_line: b"made up".to_vec(),
+        };
+        let result = to_bytes(&value).unwrap();
+        assert_eq!(result, b"@made up\r\n");
+    }
+
+    #[test]
+    fn encode_echo_command() {
+        let result = encode_command(&[b"ECHO", b"hey"]);
+        assert_eq!(result, b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n");
+    }
+
+    #[test]
+    fn encode_pipeline_concatenates_commands() {
+        let set: &[&[u8]] = &[b"SET", b"key", b"value"];
+        let get: &[&[u8]] = &[b"GET", b"key"];
+        let result = encode_pipeline(&[set, get]);
+        let mut correct = encode_command(set);
+        correct.extend(encode_command(get));
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn encode_inline_command() {
+        let result = encode_inline(&["SET", "key", "value"]);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), "SET key value\r\n");
+
+        for args in [
+            &["SET", "my key", "value"][..],
+            &["ECHO", "a\r\nb"],
+            &["ECHO", ""],
+            &[],
+        ] {
+            let result = encode_inline(args);
+            assert!(
+                matches!(result, Err(Error::InvalidValue(_))),
+                "Expected invalid value error for {:?}. Found: {:?}",
+                args,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn serialize_verbatim() {
+        let value = Value::Verbatim {
+            format: "txt".to_string(),
+            data: b"Some string".to_vec(),
+        };
+        assert_eq!(to_bytes(&value).unwrap(), b"=15\r\ntxt:Some string\r\n");
+
+        for format in ["tx", "text", "t:t", "tx1", ""] {
+            let value = Value::Verbatim {
+                format: format.to_string(),
+                data: b"Some string".to_vec(),
+            };
+            match to_bytes(&value) {
+                Err(Error::InvalidValue(msg)) => assert_eq!(
+                    msg,
+                    format!(
+                        "Verbatim string format must be 3 ASCII letters, got `{}`",
+                        format
+                    )
+                ),
+                r => panic!("Expected invalid value error. Found: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn serialize_double() {
+        assert_eq!(to_bytes(&Value::Double(2.5)).unwrap(), b",2.5\r\n");
+        assert_eq!(to_bytes(&Value::Double(10.0)).unwrap(), b",10\r\n");
+        assert_eq!(
+            to_bytes(&Value::Double(f64::NEG_INFINITY)).unwrap(),
+            b",-inf\r\n"
+        );
+        assert_eq!(to_bytes(&Value::Double(f64::NAN)).unwrap(), b",nan\r\n");
+    }
+}
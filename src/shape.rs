@@ -0,0 +1,194 @@
+use crate::value::{Value, ValueType};
+
+/// Expected structure of a reply, checked by `Value::matches_shape`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Shape {
+    /// Anything.
+    Any,
+    /// Any value of this type. Nulls are `ValueType::Null` whatever their
+    /// encoding and pushes are arrays, as they parse.
+    Type(ValueType),
+    /// Exactly this value.
+    Exact(Value),
+    /// An array with exactly these elements, in order.
+    Array(Vec<Shape>),
+    /// An array of any length, every element having this shape.
+    ArrayOf(Box<Shape>),
+    /// A map whose keys and values all have these shapes.
+    MapOf(Box<Shape>, Box<Shape>),
+    /// Any of these shapes.
+    OneOf(Vec<Shape>),
+}
+
+fn value_type(value: &Value) -> ValueType {
+    match value {
+        Value::String(_) => ValueType::String,
+        Value::Error(_) => ValueType::Error,
+        Value::Integer(_) => ValueType::Integer,
+        Value::BulkString(_) => ValueType::BulkString,
+        Value::Array(_) => ValueType::Array,
+        Value::Null(_) => ValueType::Null,
+        Value::Double(_) | Value::RawDouble { .. } => ValueType::Double,
+        Value::BigNumber(_) => ValueType::BigNumber,
+        Value::BulkError(_) => ValueType::BulkError,
+        Value::Verbatim { .. } => ValueType::Verbatim,
+        Value::Map(_) => ValueType::Map,
+        Value::Set(_) => ValueType::Set,
+        Value::WithAttributes { value, .. } => value_type(value),
+        Value::Unknown { .. } => ValueType::Unknown,
+    }
+}
+
+fn mismatch(expected: impl std::fmt::Debug, path: &str, value: &Value) -> Result<(), String> {
+    Err(format!(
+        "Expected {:?} at {}, found {:?}",
+        expected,
+        path,
+        value_type(value)
+    ))
+}
+
+fn check(value: &Value, shape: &Shape, path: &str) -> Result<(), String> {
+    // Attributes are metadata, only the value they apply to is checked.
+    if let Value::WithAttributes { value, .. } = value {
+        return check(value, shape, path);
+    }
+    match (shape, value) {
+        (Shape::Any, _) => Ok(()),
+        (Shape::Type(expected), v) if value_type(v) == *expected => Ok(()),
+        (Shape::Type(expected), v) => mismatch(expected, path, v),
+        (Shape::Exact(expected), v) if v == expected => Ok(()),
+        (Shape::Exact(expected), v) => Err(format!(
+            "Expected {:?} at {}, found {:?}",
+            expected, path, v
+        )),
+        (Shape::Array(shapes), Value::Array(items)) => {
+            if shapes.len() != items.len() {
+                return Err(format!(
+                    "Expected {} elements at {}, found {}",
+                    shapes.len(),
+                    path,
+                    items.len()
+                ));
+            }
+            for (i, (item, shape)) in items.iter().zip(shapes).enumerate() {
+                check(item, shape, &format!("{}[{}]", path, i))?;
+            }
+            Ok(())
+        }
+        (Shape::ArrayOf(shape), Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                check(item, shape, &format!("{}[{}]", path, i))?;
+            }
+            Ok(())
+        }
+        (Shape::Array(_) | Shape::ArrayOf(_), v) => mismatch(ValueType::Array, path, v),
+        (Shape::MapOf(key_shape, value_shape), Value::Map(entries)) => {
+            for (i, (k, v)) in entries.iter().enumerate() {
+                check(k, key_shape, &format!("{}.keys[{}]", path, i))?;
+                check(v, value_shape, &format!("{}.values[{}]", path, i))?;
+            }
+            Ok(())
+        }
+        (Shape::MapOf(..), v) => mismatch(ValueType::Map, path, v),
+        (Shape::OneOf(shapes), v) => {
+            if shapes.iter().any(|shape| check(v, shape, path).is_ok()) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "Expected one of {:?} at {}, found {:?}",
+                    shapes,
+                    path,
+                    value_type(v)
+                ))
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Checks the value has the given shape, e.g. in protocol tests. The
+    /// error says where the first mismatch is, `reply[1]` being the second
+    /// element of the top-level array.
+    pub fn matches_shape(&self, shape: &Shape) -> Result<(), String> {
+        check(self, shape, "reply")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::NullKind;
+
+    fn bulk(data: &str) -> Value {
+        Value::BulkString(Vec::from(data.as_bytes()))
+    }
+
+    fn two_bulk_strings() -> Shape {
+        Shape::Array(vec![
+            Shape::Type(ValueType::BulkString),
+            Shape::Type(ValueType::BulkString),
+        ])
+    }
+
+    #[test]
+    fn conforming() {
+        let value = Value::Array(vec![bulk("ECHO"), bulk("hey")]);
+        let result = value.matches_shape(&two_bulk_strings());
+        assert!(result.is_ok(), "{}", result.err().unwrap());
+
+        let value = Value::Map(vec![
+            (bulk("a"), Value::Integer(1)),
+            (bulk("b"), Value::Null(NullKind::Resp3)),
+        ]);
+        let shape = Shape::MapOf(
+            Box::new(Shape::Type(ValueType::BulkString)),
+            Box::new(Shape::OneOf(vec![
+                Shape::Type(ValueType::Integer),
+                Shape::Type(ValueType::Null),
+            ])),
+        );
+        let result = value.matches_shape(&shape);
+        assert!(result.is_ok(), "{}", result.err().unwrap());
+
+        let value = Value::Array(vec![Value::String("OK".to_string()), Value::Integer(3)]);
+        let shape = Shape::Array(vec![
+            Shape::Exact(Value::String("OK".to_string())),
+            Shape::Any,
+        ]);
+        let result = value.matches_shape(&shape);
+        assert!(result.is_ok(), "{}", result.err().unwrap());
+    }
+
+    #[test]
+    fn non_conforming() {
+        let value = Value::Array(vec![bulk("ECHO"), Value::Integer(1)]);
+        let result = value.matches_shape(&two_bulk_strings());
+        assert_eq!(
+            result,
+            Err("Expected BulkString at reply[1], found Integer".to_string())
+        );
+
+        let value = Value::Array(vec![bulk("ECHO")]);
+        let result = value.matches_shape(&two_bulk_strings());
+        assert_eq!(
+            result,
+            Err("Expected 2 elements at reply, found 1".to_string())
+        );
+
+        let result = bulk("ECHO").matches_shape(&two_bulk_strings());
+        assert_eq!(
+            result,
+            Err("Expected Array at reply, found BulkString".to_string())
+        );
+
+        let value = Value::Array(vec![Value::Array(vec![Value::Integer(1), bulk("a")])]);
+        let shape = Shape::ArrayOf(Box::new(Shape::ArrayOf(Box::new(Shape::Type(
+            ValueType::Integer,
+        )))));
+        assert_eq!(
+            value.matches_shape(&shape),
+            Err("Expected Integer at reply[0][1], found BulkString".to_string())
+        );
+    }
+}
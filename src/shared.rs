@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::value::Value;
+
+/// Counterpart of [`Value`](crate::deserializer::Value) whose bulk string
+/// bodies are reference counted, so identical bodies can share storage.
+/// Anything that isn't a bulk string or a container of them is kept as is.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SharedValue {
+    BulkString(Arc<[u8]>),
+    Array(Vec<SharedValue>),
+    Map(Vec<(SharedValue, SharedValue)>),
+    Set(Vec<SharedValue>),
+    WithAttributes {
+        attributes: Box<SharedValue>,
+        value: Box<SharedValue>,
+    },
+    Other(Value),
+}
+
+impl SharedValue {
+    /// Copies the shared data into an owned [`Value`].
+    pub fn to_owned(&self) -> Value {
+        match self {
+            SharedValue::BulkString(b) => Value::BulkString(b.to_vec()),
+            SharedValue::Array(items) => {
+                Value::Array(items.iter().map(SharedValue::to_owned).collect())
+            }
+            SharedValue::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect(),
+            ),
+            SharedValue::Set(items) => {
+                Value::Set(items.iter().map(SharedValue::to_owned).collect())
+            }
+            SharedValue::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(SharedValue::to_owned(attributes)),
+                value: Box::new(SharedValue::to_owned(value)),
+            },
+            SharedValue::Other(value) => value.clone(),
+        }
+    }
+}
+
+/// Displayed the same as the owned value.
+impl fmt::Display for SharedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_owned().fmt(f)
+    }
+}
+
+/// Converts without interning, every bulk string gets its own allocation.
+impl From<Value> for SharedValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::BulkString(b) => SharedValue::BulkString(b.into()),
+            Value::Array(items) => SharedValue::Array(items.into_iter().map(From::from).collect()),
+            Value::Map(entries) => SharedValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.into(), v.into()))
+                    .collect(),
+            ),
+            Value::Set(items) => SharedValue::Set(items.into_iter().map(From::from).collect()),
+            Value::WithAttributes { attributes, value } => SharedValue::WithAttributes {
+                attributes: Box::new((*attributes).into()),
+                value: Box::new((*value).into()),
+            },
+            value => SharedValue::Other(value),
+        }
+    }
+}
+
+// Below this many bodies, pruning the interner isn't worth it.
+const MIN_PRUNE_LEN: usize = 64;
+
+/// Hands out a single `Arc<[u8]>` per distinct bulk body it has seen.
+///
+/// Bodies nobody else holds anymore are dropped whenever the interner has
+/// doubled in size since the last time, so it grows with the bodies still in
+/// use rather than with every body ever seen.
+#[derive(Debug)]
+pub struct Interner {
+    seen: HashSet<Arc<[u8]>>,
+    prune_at: usize,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Interner::new()
+    }
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner {
+            seen: HashSet::new(),
+            prune_at: MIN_PRUNE_LEN,
+        }
+    }
+
+    /// Number of distinct bodies currently held.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    pub fn intern(&mut self, data: &[u8]) -> Arc<[u8]> {
+        if let Some(shared) = self.seen.get(data) {
+            return shared.clone();
+        }
+        if self.seen.len() >= self.prune_at {
+            self.prune();
+        }
+        let shared: Arc<[u8]> = data.into();
+        self.seen.insert(shared.clone());
+        shared
+    }
+
+    /// Drops the bodies only the interner still holds.
+    pub fn prune(&mut self) {
+        self.seen.retain(|shared| Arc::strong_count(shared) > 1);
+        self.prune_at = (self.seen.len() * 2).max(MIN_PRUNE_LEN);
+    }
+
+    /// Converts `value`, sharing bulk bodies with everything interned before.
+    pub fn intern_value(&mut self, value: Value) -> SharedValue {
+        match value {
+            Value::BulkString(b) => SharedValue::BulkString(self.intern(&b)),
+            Value::Array(items) => SharedValue::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.intern_value(item))
+                    .collect(),
+            ),
+            Value::Map(entries) => SharedValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (self.intern_value(k), self.intern_value(v)))
+                    .collect(),
+            ),
+            Value::Set(items) => SharedValue::Set(
+                items
+                    .into_iter()
+                    .map(|item| self.intern_value(item))
+                    .collect(),
+            ),
+            Value::WithAttributes { attributes, value } => SharedValue::WithAttributes {
+                attributes: Box::new(self.intern_value(*attributes)),
+                value: Box::new(self.intern_value(*value)),
+            },
+            value => SharedValue::Other(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_drops_unused_bodies() {
+        let mut interner = Interner::new();
+        let kept = interner.intern(b"field");
+        for i in 0..10_000 {
+            interner.intern(i.to_string().as_bytes());
+        }
+        assert!(interner.len() <= 2 * MIN_PRUNE_LEN, "{}", interner.len());
+        assert!(Arc::ptr_eq(&kept, &interner.intern(b"field")));
+        interner.prune();
+        assert_eq!(interner.len(), 1);
+        drop(kept);
+        interner.prune();
+        assert!(interner.is_empty());
+    }
+}
@@ -0,0 +1,32 @@
+/// Receives the parts of a frame as they're parsed, see
+/// [`parse_events`](crate::deserializer::parse_events). Nothing is collected
+/// into a [`Value`](crate::deserializer::Value), so memory use is bounded by
+/// the largest bulk string or line of a reply, not by the whole reply.
+///
+/// Every method does nothing by default, implement the ones you need.
+/// Aggregates call their `*_start` method with the declared number of
+/// elements (entries for maps), then the elements, then `*_end`. Pushes are
+/// reported as arrays, as they are by `parse`.
+#[allow(unused_variables)]
+pub trait ValueSink {
+    fn on_string(&mut self, s: &str) {}
+    fn on_error(&mut self, e: &str) {}
+    fn on_integer(&mut self, i: i64) {}
+    fn on_double(&mut self, d: f64) {}
+    fn on_big_number(&mut self, n: &str) {}
+    fn on_bulk(&mut self, b: &[u8]) {}
+    fn on_bulk_error(&mut self, b: &[u8]) {}
+    fn on_verbatim(&mut self, format: &str, data: &[u8]) {}
+    fn on_null(&mut self) {}
+    fn on_array_start(&mut self, length: usize) {}
+    fn on_array_end(&mut self) {}
+    fn on_set_start(&mut self, length: usize) {}
+    fn on_set_end(&mut self) {}
+    fn on_map_start(&mut self, length: usize) {}
+    fn on_map_end(&mut self) {}
+    /// Called around the attribute map; the value they apply to follows
+    /// `on_attributes_end`.
+    fn on_attributes_start(&mut self, length: usize) {}
+    fn on_attributes_end(&mut self) {}
+    fn on_unknown(&mut self, prefix: u8, raw_line: &[u8]) {}
+}
@@ -0,0 +1,1487 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::deserializer::{self, Error, Result};
+use crate::from_value::{unexpected, FromRespValue};
+use crate::serializer::{self, ProtocolVersion};
+
+/// A parsed RESP value.
+///
+/// Variants are added as more of RESP3 is supported, so matches outside this
+/// crate need a wildcard arm, typically treating the value as unexpected.
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum Value {
+    String(String),      // https://redis.io/docs/reference/protocol-spec/#simple-strings
+    Error(String),       // https://redis.io/docs/reference/protocol-spec/#simple-errors
+    Integer(i64),        // https://redis.io/docs/reference/protocol-spec/#integers
+    BulkString(Vec<u8>), // https://redis.io/docs/reference/protocol-spec/#bulk-strings
+    Array(Vec<Value>),   // https://redis.io/docs/reference/protocol-spec/#arrays
+    /// RESP2 null bulk strings and arrays (`$-1`, `*-1`) as well as the RESP3
+    /// null (`_`) all parse to this single variant, tagged with the encoding
+    /// it came from.
+    Null(NullKind), // https://redis.io/docs/reference/protocol-spec/#nulls
+    Double(f64),         // https://redis.io/docs/reference/protocol-spec/#doubles
+    BigNumber(String),   // https://redis.io/docs/reference/protocol-spec/#big-numbers
+    BulkError(Vec<u8>),  // https://redis.io/docs/reference/protocol-spec/#bulk-errors
+    /// Text tagged with a three character format, `txt` or `mkd`.
+    Verbatim {
+        format: String,
+        data: Vec<u8>,
+    }, // https://redis.io/docs/reference/protocol-spec/#verbatim-strings
+    /// A double along with the text it was parsed from, only produced when
+    /// the deserializer is configured to keep it. Serialized from `raw`, so
+    /// proxied replies come out byte for byte.
+    RawDouble {
+        value: f64,
+        raw: String,
+    },
+    Map(Vec<(Value, Value)>), // https://redis.io/docs/reference/protocol-spec/#maps
+    Set(Vec<Value>),          // https://redis.io/docs/reference/protocol-spec/#sets
+    WithAttributes {
+        attributes: Box<Value>,
+        value: Box<Value>,
+    }, // https://redis.io/docs/reference/protocol-spec/#attributes
+    /// A frame with a type prefix this crate doesn't know, only produced when
+    /// the deserializer is configured to let them through.
+    Unknown {
+        prefix: u8,
+        raw_line: Vec<u8>,
+    },
+}
+
+/// Encoding a null was read from, so it's written back the same way.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum NullKind {
+    /// `$-1`, the RESP2 null bulk string.
+    #[default]
+    BulkString,
+    /// `*-1`, the RESP2 null array.
+    Array,
+    /// `_`, the RESP3 null.
+    Resp3,
+}
+
+/// Kind of a frame, as announced by its type prefix byte.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ValueType {
+    String,
+    Error,
+    Integer,
+    BulkString,
+    Array,
+    Double,
+    BigNumber,
+    BulkError,
+    Verbatim,
+    Push,
+    Map,
+    Set,
+    Attribute,
+    Null,
+    Unknown,
+}
+
+impl ValueType {
+    pub fn from_prefix(prefix: u8) -> Option<ValueType> {
+        match prefix {
+            b'+' => Some(ValueType::String),
+            b'-' => Some(ValueType::Error),
+            b':' => Some(ValueType::Integer),
+            b'$' => Some(ValueType::BulkString),
+            b'*' => Some(ValueType::Array),
+            b',' => Some(ValueType::Double),
+            b'(' => Some(ValueType::BigNumber),
+            b'!' => Some(ValueType::BulkError),
+            b'=' => Some(ValueType::Verbatim),
+            b'>' => Some(ValueType::Push),
+            b'%' => Some(ValueType::Map),
+            b'~' => Some(ValueType::Set),
+            b'|' => Some(ValueType::Attribute),
+            b'_' => Some(ValueType::Null),
+            _ => None,
+        }
+    }
+}
+
+impl Value {
+    /// Returns the message of a simple or bulk error, `None` for other
+    /// variants and for bulk errors that aren't valid UTF-8.
+    pub fn as_error(&self) -> Option<&str> {
+        match self {
+            Value::Error(e) => Some(e),
+            Value::BulkError(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+
+    /// Simple and bulk errors.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Value::Error(_) | Value::BulkError(_))
+    }
+
+    /// Nulls, whatever their encoding.
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Null(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Value::Array(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Value::Integer(_))
+    }
+
+    /// Simple strings only, see `is_bulk` for bulk strings.
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::String(_))
+    }
+
+    pub fn is_bulk(&self) -> bool {
+        matches!(self, Value::BulkString(_))
+    }
+
+    /// Tells whether the value is a well formed command: a non-empty array
+    /// of bulk strings.
+    pub fn is_command(&self) -> bool {
+        match self {
+            Value::Array(items) => !items.is_empty() && items.iter().all(Value::is_bulk),
+            _ => false,
+        }
+    }
+
+    /// Tells whether the value is a command named `name`, ignoring ASCII
+    /// case.
+    pub fn command_matches(&self, name: &str) -> bool {
+        match self {
+            Value::Array(items) if self.is_command() => match &items[0] {
+                Value::BulkString(b) => b.eq_ignore_ascii_case(name.as_bytes()),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the name of a command, uppercased, or `None` if the value
+    /// isn't a command or its name isn't valid UTF-8.
+    pub fn command_name(&self) -> Option<String> {
+        match self {
+            Value::Array(items) if self.is_command() => match &items[0] {
+                Value::BulkString(name) => std::str::from_utf8(name).ok().map(str::to_uppercase),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a simple or bulk string, with an error naming what
+    /// was found instead, or saying the bulk string isn't valid UTF-8.
+    pub fn try_str(&self) -> Result<&str> {
+        match self {
+            Value::String(s) => Ok(s),
+            Value::BulkString(b) => std::str::from_utf8(b)
+                .map_err(|_| Error::InvalidValue("Non UTF-8 bulk string".to_string())),
+            v => unexpected("string", v),
+        }
+    }
+
+    /// Returns the format of a verbatim string, such as `txt` or `mkd`.
+    pub fn verbatim_format(&self) -> Option<&str> {
+        match self {
+            Value::Verbatim { format, .. } => Some(format),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a verbatim string, without its format.
+    pub fn verbatim_data(&self) -> Option<&[u8]> {
+        match self {
+            Value::Verbatim { data, .. } => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns the entries of a map, `None` for other variants.
+    pub fn as_map(&self) -> Option<&[(Value, Value)]> {
+        match self {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Iterates over the entries of a map, yields nothing for other variants.
+    pub fn pairs(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        self.as_map()
+            .unwrap_or_default()
+            .iter()
+            .map(|(k, v)| (k, v))
+    }
+
+    /// Groups a flat key/value array, as RESP2 replies such as `HGETALL` use,
+    /// into pairs. `None` for arrays of odd length and other variants.
+    pub fn array_as_pairs(&self) -> Option<Vec<(&Value, &Value)>> {
+        match self {
+            Value::Array(items) if items.len() % 2 == 0 => {
+                Some(items.chunks_exact(2).map(|p| (&p[0], &p[1])).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// Serializes the value to RESP for quick inspection, escaping bytes that
+    /// aren't valid UTF-8 as `\xNN`. Use the serializer for the wire format.
+    ///
+    /// Never fails: values the serializer rejects are written anyway, with
+    /// the line breaks simple strings and the like can't hold escaped as `\r`
+    /// and `\n`.
+    pub fn encode_lossy(&self) -> String {
+        let mut result = String::new();
+        write_lossy(&mut result, self);
+        result
+    }
+
+    /// Encodes the value so that logically equal replies give the same bytes,
+    /// e.g. as a cache key: set elements and map entries are sorted and every
+    /// null is written as `_`. Unlike the serializer, this doesn't preserve
+    /// the original encoding. Fails for values the serializer rejects.
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>> {
+        encode_canonical(&self.canonical()?)
+    }
+
+    fn canonical(&self) -> Result<Value> {
+        // Sorting by encoding is arbitrary but deterministic.
+        fn sorted(items: &[Value]) -> Result<Vec<Value>> {
+            let mut keyed = items
+                .iter()
+                .map(|v| {
+                    let v = v.canonical()?;
+                    Ok((encode_canonical(&v)?, v))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            keyed.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(keyed.into_iter().map(|(_, v)| v).collect())
+        }
+        fn sorted_map(entries: &[(Value, Value)]) -> Result<Vec<(Value, Value)>> {
+            let mut keyed = entries
+                .iter()
+                .map(|(k, v)| {
+                    let (k, v) = (k.canonical()?, v.canonical()?);
+                    Ok(((encode_canonical(&k)?, encode_canonical(&v)?), (k, v)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            keyed.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(keyed.into_iter().map(|(_, entry)| entry).collect())
+        }
+        Ok(match self {
+            Value::Array(items) => {
+                Value::Array(items.iter().map(Value::canonical).collect::<Result<_>>()?)
+            }
+            Value::Set(items) => Value::Set(sorted(items)?),
+            Value::Map(entries) => Value::Map(sorted_map(entries)?),
+            Value::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(attributes.canonical()?),
+                value: Box::new(value.canonical()?),
+            },
+            v => v.clone(),
+        })
+    }
+
+    /// Downgrades a RESP3 reply for a RESP2 client, the way Redis does: maps
+    /// become flat arrays of keys and values, sets become arrays, doubles,
+    /// big numbers and verbatim strings become bulk strings, bulk errors
+    /// become simple errors and the RESP3 null becomes `$-1`. Attributes are
+    /// dropped. RESP2 values are left as is.
+    pub fn to_resp2(&self) -> Value {
+        match self {
+            Value::Map(entries) => Value::Array(
+                entries
+                    .iter()
+                    .flat_map(|(k, v)| [k.to_resp2(), v.to_resp2()])
+                    .collect(),
+            ),
+            Value::Array(items) | Value::Set(items) => {
+                Value::Array(items.iter().map(Value::to_resp2).collect())
+            }
+            Value::Double(d) if d.is_nan() => Value::BulkString(b"nan".to_vec()),
+            Value::Double(d) => Value::BulkString(d.to_string().into_bytes()),
+            Value::RawDouble { raw, .. } => Value::BulkString(raw.clone().into_bytes()),
+            Value::BigNumber(n) => Value::BulkString(n.clone().into_bytes()),
+            Value::Verbatim { data, .. } => Value::BulkString(data.clone()),
+            // Simple errors can't hold line breaks.
+            Value::BulkError(e) => {
+                Value::Error(String::from_utf8_lossy(e).replace(['\r', '\n'], " "))
+            }
+            Value::Null(NullKind::Resp3) => Value::Null(NullKind::BulkString),
+            Value::WithAttributes { value, .. } => value.to_resp2(),
+            v => v.clone(),
+        }
+    }
+
+    /// Returns the numeric value of an integer, double or big number reply,
+    /// whichever prefix the server picked. Big numbers that don't fit in an
+    /// `f64` give `None`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Double(d) | Value::RawDouble { value: d, .. } => Some(*d),
+            Value::BigNumber(n) => n.parse::<f64>().ok().filter(|n| n.is_finite()),
+            _ => None,
+        }
+    }
+
+    /// Estimates the heap memory held by the value and everything nested in
+    /// it, from the capacity of its buffers. The value itself, typically on
+    /// the stack or inside its parent, isn't counted.
+    pub fn approx_memory_size(&self) -> usize {
+        let value_size = std::mem::size_of::<Value>();
+        match self {
+            Value::String(s) | Value::Error(s) | Value::BigNumber(s) => s.capacity(),
+            Value::BulkString(b) | Value::BulkError(b) => b.capacity(),
+            Value::RawDouble { raw, .. } => raw.capacity(),
+            Value::Verbatim { format, data } => format.capacity() + data.capacity(),
+            Value::Unknown { raw_line, .. } => raw_line.capacity(),
+            Value::Array(items) | Value::Set(items) => {
+                items.capacity() * value_size
+                    + items.iter().map(Value::approx_memory_size).sum::<usize>()
+            }
+            Value::Map(entries) => {
+                entries.capacity() * 2 * value_size
+                    + entries
+                        .iter()
+                        .map(|(k, v)| k.approx_memory_size() + v.approx_memory_size())
+                        .sum::<usize>()
+            }
+            Value::WithAttributes { attributes, value } => {
+                2 * value_size + attributes.approx_memory_size() + value.approx_memory_size()
+            }
+            Value::Integer(_) | Value::Double(_) | Value::Null(_) => 0,
+        }
+    }
+
+    /// Reads an integer reply counting seconds, such as `TTL`'s. Negative
+    /// integers, like the `-1` (no expiry) and `-2` (no key) sentinels, and
+    /// other variants give `None`.
+    pub fn as_duration_secs(&self) -> Option<Duration> {
+        match self {
+            Value::Integer(i) => u64::try_from(*i).ok().map(Duration::from_secs),
+            _ => None,
+        }
+    }
+
+    /// Same as `as_duration_secs` for replies counting milliseconds, such as
+    /// `PTTL`'s.
+    pub fn as_duration_millis(&self) -> Option<Duration> {
+        match self {
+            Value::Integer(i) => u64::try_from(*i).ok().map(Duration::from_millis),
+            _ => None,
+        }
+    }
+
+    /// Returns the nesting depth: 1 for scalars and empty aggregates, one more
+    /// than the deepest element for aggregates. Attributes count as a level,
+    /// the same way the deserializer sees them.
+    pub fn depth(&self) -> usize {
+        let children = match self {
+            Value::Array(items) | Value::Set(items) => items.iter().map(Value::depth).max(),
+            Value::Map(entries) => entries.iter().map(|(k, v)| k.depth().max(v.depth())).max(),
+            Value::WithAttributes { attributes, value } => {
+                Some(attributes.depth().saturating_sub(1).max(value.depth()))
+            }
+            _ => None,
+        };
+        1 + children.unwrap_or(0)
+    }
+
+    /// Folds `f` over the value and everything nested in it, depth first,
+    /// visiting an aggregate before its elements.
+    pub fn fold<B, F: FnMut(B, &Value) -> B>(&self, init: B, mut f: F) -> B {
+        self.fold_with(init, &mut f)
+    }
+
+    fn fold_with<B, F: FnMut(B, &Value) -> B>(&self, init: B, f: &mut F) -> B {
+        let acc = f(init, self);
+        match self {
+            Value::Array(items) | Value::Set(items) => {
+                items.iter().fold(acc, |acc, item| item.fold_with(acc, f))
+            }
+            Value::Map(entries) => entries
+                .iter()
+                .fold(acc, |acc, (k, v)| v.fold_with(k.fold_with(acc, f), f)),
+            Value::WithAttributes { attributes, value } => {
+                value.fold_with(attributes.fold_with(acc, f), f)
+            }
+            _ => acc,
+        }
+    }
+
+    /// Replaces the body of every bulk string, however deeply nested, with
+    /// the result of `f`. Everything else is left as is.
+    pub fn map_bulk<F: FnMut(&[u8]) -> Vec<u8>>(self, mut f: F) -> Value {
+        self.map_bulk_with(&mut f)
+    }
+
+    fn map_bulk_with<F: FnMut(&[u8]) -> Vec<u8>>(self, f: &mut F) -> Value {
+        match self {
+            Value::BulkString(b) => Value::BulkString(f(&b)),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|v| v.map_bulk_with(f)).collect())
+            }
+            Value::Set(items) => {
+                Value::Set(items.into_iter().map(|v| v.map_bulk_with(f)).collect())
+            }
+            Value::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.map_bulk_with(f), v.map_bulk_with(f)))
+                    .collect(),
+            ),
+            Value::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(attributes.map_bulk_with(f)),
+                value: Box::new(value.map_bulk_with(f)),
+            },
+            v => v,
+        }
+    }
+
+    /// Replaces every null, however deeply nested, with a copy of `with`.
+    pub fn replace_nulls(self, with: Value) -> Value {
+        self.replace_nulls_with(&with)
+    }
+
+    fn replace_nulls_with(self, with: &Value) -> Value {
+        match self {
+            Value::Null(_) => with.clone(),
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|v| v.replace_nulls_with(with))
+                    .collect(),
+            ),
+            Value::Set(items) => Value::Set(
+                items
+                    .into_iter()
+                    .map(|v| v.replace_nulls_with(with))
+                    .collect(),
+            ),
+            Value::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.replace_nulls_with(with), v.replace_nulls_with(with)))
+                    .collect(),
+            ),
+            Value::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(attributes.replace_nulls_with(with)),
+                value: Box::new(value.replace_nulls_with(with)),
+            },
+            v => v,
+        }
+    }
+
+    /// Drops attributes, however deeply nested, keeping the values they
+    /// applied to.
+    pub fn strip_attributes(self) -> Value {
+        match self {
+            Value::WithAttributes { value, .. } => value.strip_attributes(),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(Value::strip_attributes).collect())
+            }
+            Value::Set(items) => {
+                Value::Set(items.into_iter().map(Value::strip_attributes).collect())
+            }
+            Value::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.strip_attributes(), v.strip_attributes()))
+                    .collect(),
+            ),
+            v => v,
+        }
+    }
+
+    /// Returns a copy where bulk strings longer than `max_bytes` are cut down
+    /// to `max_bytes` followed by `...`, leaving the structure intact.
+    ///
+    /// Handy for logging replies that may carry huge payloads.
+    pub fn truncated(&self, max_bytes: usize) -> Value {
+        match self {
+            Value::BulkString(b) if b.len() > max_bytes => {
+                let mut result = b[..max_bytes].to_vec();
+                result.extend_from_slice(b"...");
+                Value::BulkString(result)
+            }
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| v.truncated(max_bytes)).collect())
+            }
+            Value::Set(items) => Value::Set(items.iter().map(|v| v.truncated(max_bytes)).collect()),
+            Value::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.truncated(max_bytes), v.truncated(max_bytes)))
+                    .collect(),
+            ),
+            Value::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(attributes.truncated(max_bytes)),
+                value: Box::new(value.truncated(max_bytes)),
+            },
+            v => v.clone(),
+        }
+    }
+
+    /// Returns a copy nested at most `max` levels deep, as counted by
+    /// `depth`: non-empty aggregates that would go past it are replaced with
+    /// the simple string `...`. A `max` of 0 is treated as 1.
+    ///
+    /// Handy for displaying untrusted replies.
+    pub fn prune_depth(&self, max: usize) -> Value {
+        let max = max.max(1);
+        let pruned = match self {
+            Value::Array(items) | Value::Set(items) => !items.is_empty(),
+            Value::Map(entries) => !entries.is_empty(),
+            _ => false,
+        };
+        if pruned && max == 1 {
+            return Value::String("...".to_string());
+        }
+        match self {
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| v.prune_depth(max - 1)).collect())
+            }
+            Value::Set(items) => Value::Set(items.iter().map(|v| v.prune_depth(max - 1)).collect()),
+            Value::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.prune_depth(max - 1), v.prune_depth(max - 1)))
+                    .collect(),
+            ),
+            // Attributes don't add a level to the value they apply to.
+            Value::WithAttributes { attributes, value } => Value::WithAttributes {
+                attributes: Box::new(attributes.prune_depth(max.saturating_add(1))),
+                value: Box::new(value.prune_depth(max)),
+            },
+            v => v.clone(),
+        }
+    }
+
+    /// Matches a simple or bulk string against a Redis glob `pattern`
+    /// (`*`, `?`, `[...]` and `\` escapes), as used by `KEYS` and `SCAN`.
+    ///
+    /// Any other variant never matches.
+    pub fn matches_glob(&self, pattern: &str) -> bool {
+        match self {
+            Value::String(s) => glob_match(pattern.as_bytes(), s.as_bytes()),
+            Value::BulkString(b) => glob_match(pattern.as_bytes(), b),
+            _ => false,
+        }
+    }
+
+    /// Converts a map, or a flat key/value array as RESP2 replies such as
+    /// `CONFIG GET` use, into a `HashMap` keyed by string. Keys must be
+    /// simple or UTF-8 bulk strings.
+    pub fn into_string_map(self) -> Result<HashMap<String, Value>> {
+        HashMap::from_value(self)
+    }
+
+    /// Concatenates the bulk strings of an array with `sep` in between. `None`
+    /// if any element isn't a bulk string, or for other variants.
+    pub fn join_bulk(&self, sep: &[u8]) -> Option<Vec<u8>> {
+        let items = match self {
+            Value::Array(items) => items,
+            _ => return None,
+        };
+        let mut result = vec![];
+        for (i, item) in items.iter().enumerate() {
+            match item {
+                Value::BulkString(b) => {
+                    if i > 0 {
+                        result.extend_from_slice(sep);
+                    }
+                    result.extend_from_slice(b);
+                }
+                _ => return None,
+            }
+        }
+        Some(result)
+    }
+
+    /// Returns the element of a one-element array, or the value unchanged
+    /// otherwise.
+    pub fn unwrap_singleton(self) -> Value {
+        match self {
+            Value::Array(mut items) if items.len() == 1 => items.pop().unwrap(),
+            v => v,
+        }
+    }
+
+    /// Keeps only the elements of an array or set for which `f` returns
+    /// `true`. Does nothing for other variants.
+    pub fn retain<F: FnMut(&Value) -> bool>(&mut self, f: F) {
+        if let Value::Array(items) | Value::Set(items) = self {
+            items.retain(f);
+        }
+    }
+
+    /// Keeps only the entries of a map for which `f` returns `true`. Does
+    /// nothing for other variants.
+    pub fn retain_map<F: FnMut(&Value, &Value) -> bool>(&mut self, mut f: F) {
+        if let Value::Map(entries) = self {
+            entries.retain(|(k, v)| f(k, v));
+        }
+    }
+
+    /// Sorts the elements of an array or set, provided they're all integers,
+    /// all doubles, all simple strings or all bulk strings. Mixed or other
+    /// elements are left in place, as are other variants. Nested aggregates
+    /// aren't sorted.
+    pub fn sort(&mut self) {
+        fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+            match (a, b) {
+                (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+                (Value::Double(a), Value::Double(b)) => Some(a.total_cmp(b)),
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                (Value::BulkString(a), Value::BulkString(b)) => Some(a.cmp(b)),
+                _ => None,
+            }
+        }
+        if let Value::Array(items) | Value::Set(items) = self {
+            // Elements of the same kind are always comparable, checking
+            // neighbours is enough.
+            if items.windows(2).all(|w| compare(&w[0], &w[1]).is_some()) {
+                items.sort_by(|a, b| compare(a, b).unwrap_or(Ordering::Equal));
+            }
+        }
+    }
+
+    /// Tells whether an array or set has an element equal to `needle`. Always
+    /// `false` for other variants.
+    pub fn contains(&self, needle: &Value) -> bool {
+        match self {
+            Value::Array(items) | Value::Set(items) => items.contains(needle),
+            _ => false,
+        }
+    }
+
+    /// Appends `value` to an array, failing for any other variant.
+    pub fn push(&mut self, value: Value) -> Result<()> {
+        match self {
+            Value::Array(items) => {
+                items.push(value);
+                Ok(())
+            }
+            _ => Err(Error::InvalidValue(
+                "Can't push onto a non-array value".to_string(),
+            )),
+        }
+    }
+
+    /// Removes the last element of an array, `None` if it's empty. Fails for
+    /// any other variant.
+    pub fn pop(&mut self) -> Result<Option<Value>> {
+        match self {
+            Value::Array(items) => Ok(items.pop()),
+            _ => Err(Error::InvalidValue(
+                "Can't pop from a non-array value".to_string(),
+            )),
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Value {
+    type Error = Error;
+
+    /// Parses the first frame in `data`, like `from_bytes`.
+    fn try_from(data: &[u8]) -> Result<Value> {
+        deserializer::from_bytes(data)
+    }
+}
+
+impl FromStr for Value {
+    type Err = Error;
+
+    /// Parses the first frame in `s`, like `from_string`.
+    fn from_str(s: &str) -> Result<Value> {
+        deserializer::from_string(s)
+    }
+}
+
+fn push_escaped(out: &mut String, bytes: &[u8], escape_line_breaks: bool) {
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\r' if escape_line_breaks => out.push_str("\\r"),
+                '\n' if escape_line_breaks => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        for c in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", c));
+        }
+    }
+}
+
+fn push_line(out: &mut String, prefix: char, line: &[u8]) {
+    out.push(prefix);
+    push_escaped(out, line, true);
+    out.push_str("\r\n");
+}
+
+fn push_blob(out: &mut String, prefix: char, data: &[u8]) {
+    out.push_str(&format!("{}{}\r\n", prefix, data.len()));
+    push_escaped(out, data, false);
+    out.push_str("\r\n");
+}
+
+// Encodes a value already put in canonical form.
+fn encode_canonical(value: &Value) -> Result<Vec<u8>> {
+    serializer::to_bytes_with_version(value, ProtocolVersion::Resp3)
+}
+
+// Lenient counterpart of the serializer for `encode_lossy`.
+fn write_lossy(out: &mut String, value: &Value) {
+    match value {
+        Value::String(s) => push_line(out, '+', s.as_bytes()),
+        Value::Error(e) => push_line(out, '-', e.as_bytes()),
+        Value::Integer(i) => out.push_str(&format!(":{}\r\n", i)),
+        Value::Double(d) if d.is_nan() => out.push_str(",nan\r\n"),
+        Value::Double(d) => out.push_str(&format!(",{}\r\n", d)),
+        Value::RawDouble { raw, .. } => push_line(out, ',', raw.as_bytes()),
+        Value::BigNumber(n) => push_line(out, '(', n.as_bytes()),
+        Value::BulkString(b) => push_blob(out, '$', b),
+        Value::BulkError(b) => push_blob(out, '!', b),
+        Value::Verbatim { format, data } => {
+            out.push_str(&format!("={}\r\n", format.len() + 1 + data.len()));
+            push_escaped(out, format.as_bytes(), true);
+            out.push(':');
+            push_escaped(out, data, false);
+            out.push_str("\r\n");
+        }
+        Value::Null(NullKind::BulkString) => out.push_str("$-1\r\n"),
+        Value::Null(NullKind::Array) => out.push_str("*-1\r\n"),
+        Value::Null(NullKind::Resp3) => out.push_str("_\r\n"),
+        Value::Array(items) | Value::Set(items) => {
+            let prefix = if matches!(value, Value::Set(_)) {
+                '~'
+            } else {
+                '*'
+            };
+            out.push_str(&format!("{}{}\r\n", prefix, items.len()));
+            for item in items {
+                write_lossy(out, item);
+            }
+        }
+        Value::Map(entries) => {
+            out.push_str(&format!("%{}\r\n", entries.len()));
+            for (k, v) in entries {
+                write_lossy(out, k);
+                write_lossy(out, v);
+            }
+        }
+        Value::WithAttributes { attributes, value } => {
+            match attributes.as_ref() {
+                Value::Map(entries) => {
+                    out.push_str(&format!("|{}\r\n", entries.len()));
+                    for (k, v) in entries {
+                        write_lossy(out, k);
+                        write_lossy(out, v);
+                    }
+                }
+                // Not a valid attribute, but still worth seeing.
+                attributes => {
+                    out.push('|');
+                    write_lossy(out, attributes);
+                }
+            }
+            write_lossy(out, value);
+        }
+        Value::Unknown { prefix, raw_line } => {
+            push_line(out, *prefix as char, raw_line);
+        }
+    }
+}
+
+// Matches the pattern token starting at `p` against the byte `c`, returning
+// where the next token starts.
+fn glob_match_one(pattern: &[u8], mut p: usize, c: u8) -> Option<usize> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'[' => {
+            p += 1;
+            let negate = pattern.get(p) == Some(&b'^');
+            if negate {
+                p += 1;
+            }
+            let mut matched = false;
+            while p < pattern.len() && pattern[p] != b']' {
+                if pattern[p] == b'\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    matched |= pattern[p] == c;
+                } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                    let (start, end) = if pattern[p] <= pattern[p + 2] {
+                        (pattern[p], pattern[p + 2])
+                    } else {
+                        (pattern[p + 2], pattern[p])
+                    };
+                    matched |= (start..=end).contains(&c);
+                    p += 2;
+                } else {
+                    matched |= pattern[p] == c;
+                }
+                p += 1;
+            }
+            // An unterminated class runs to the end of the pattern.
+            (matched != negate).then_some((p + 1).min(pattern.len()))
+        }
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+// Same semantics as `stringmatchlen` from the Redis sources, but only ever
+// backtracks to the last `*`, so matching stays linear in the length of the
+// string for each pattern byte instead of blowing up exponentially with the
+// number of stars (CVE-2022-36021).
+fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*`: the pattern past it and the string
+    // position it has swallowed up to.
+    let mut star: Option<(usize, usize)> = None;
+    loop {
+        if pattern.get(p) == Some(&b'*') {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+            star = Some((p, s));
+            continue;
+        }
+        if s == string.len() {
+            // Backtracking only ever gives the star more of the string.
+            return p == pattern.len();
+        }
+        if p < pattern.len() {
+            if let Some(next) = glob_match_one(pattern, p, string[s]) {
+                p = next;
+                s += 1;
+                continue;
+            }
+        }
+        match star {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                star = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(data: &str) -> Value {
+        Value::BulkString(Vec::from(data.as_bytes()))
+    }
+
+    #[test]
+    fn parse_conversions() {
+        let correct = Value::Array(vec![bulk("ECHO"), bulk("hey")]);
+        let result = Value::try_from(&b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n"[..]);
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), correct);
+        let result = "*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n".parse::<Value>();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), correct);
+        let result = "*2\r\n".parse::<Value>();
+        assert!(
+            matches!(result, Err(Error::EndOfStream)),
+            "Expected end of stream. Found: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let mut value = Value::Array(vec![]);
+        for item in [bulk("SET"), bulk("key"), Value::Integer(1)] {
+            let result = value.push(item);
+            assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        }
+        let correct = Value::Array(vec![bulk("SET"), bulk("key"), Value::Integer(1)]);
+        assert_eq!(value, correct);
+
+        assert_eq!(value.pop().unwrap(), Some(Value::Integer(1)));
+        assert_eq!(value, Value::Array(vec![bulk("SET"), bulk("key")]));
+        assert_eq!(Value::Array(vec![]).pop().unwrap(), None);
+
+        let mut value = bulk("SET");
+        assert!(
+            matches!(
+                value.push(Value::Null(NullKind::Resp3)),
+                Err(Error::InvalidValue(_))
+            ),
+            "Bulk strings shouldn't accept elements. Found: {:?}",
+            value
+        );
+        assert!(value.pop().is_err());
+    }
+
+    #[test]
+    fn try_str() {
+        let value = bulk("hey");
+        let result = value.try_str();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap(), "hey");
+        assert_eq!(Value::String("OK".to_string()).try_str().unwrap(), "OK");
+
+        match Value::Integer(1).try_str() {
+            Err(Error::InvalidValue(msg)) => assert_eq!(msg, "Expected string, found integer"),
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+        match Value::BulkString(vec![b'a', 0xff]).try_str() {
+            Err(Error::InvalidValue(msg)) => assert_eq!(msg, "Non UTF-8 bulk string"),
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn verbatim_parts() {
+        let value = Value::Verbatim {
+            format: "mkd".to_string(),
+            data: b"# Title".to_vec(),
+        };
+        assert_eq!(value.verbatim_format(), Some("mkd"));
+        assert_eq!(value.verbatim_data(), Some(&b"# Title"[..]));
+        assert_eq!(bulk("mkd:# Title").verbatim_format(), None);
+        assert_eq!(bulk("mkd:# Title").verbatim_data(), None);
+    }
+
+    #[test]
+    fn as_map() {
+        let value = Value::Map(vec![(bulk("a"), Value::Integer(1))]);
+        assert_eq!(value.as_map(), Some(&[(bulk("a"), Value::Integer(1))][..]));
+        assert_eq!(Value::Map(vec![]).as_map(), Some(&[][..]));
+        let value = Value::Array(vec![bulk("a"), Value::Integer(1)]);
+        assert_eq!(value.as_map(), None);
+    }
+
+    #[test]
+    fn pairs() {
+        let value = Value::Map(vec![
+            (bulk("a"), Value::Integer(1)),
+            (bulk("b"), Value::Integer(2)),
+        ]);
+        let keys: Vec<&Value> = value.pairs().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![&bulk("a"), &bulk("b")]);
+        let value = Value::Array(vec![bulk("a"), Value::Integer(1)]);
+        assert_eq!(value.pairs().count(), 0);
+    }
+
+    #[test]
+    fn array_as_pairs() {
+        let value = Value::Array(vec![
+            bulk("a"),
+            Value::Integer(1),
+            bulk("b"),
+            Value::Integer(2),
+        ]);
+        let (a, b) = (bulk("a"), bulk("b"));
+        let result = value.array_as_pairs();
+        let correct = vec![(&a, &Value::Integer(1)), (&b, &Value::Integer(2))];
+        assert_eq!(result, Some(correct));
+        let value = Value::Array(vec![bulk("a"), Value::Integer(1), bulk("b")]);
+        assert_eq!(value.array_as_pairs(), None);
+        assert_eq!(Value::Map(vec![]).array_as_pairs(), None);
+    }
+
+    #[test]
+    fn string_map() {
+        let value = Value::Map(vec![
+            (bulk("maxmemory"), bulk("0")),
+            (Value::String("port".to_string()), Value::Integer(6379)),
+        ]);
+        let result = value.into_string_map();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        let result = result.unwrap();
+        let correct = HashMap::from([
+            ("maxmemory".to_string(), bulk("0")),
+            ("port".to_string(), Value::Integer(6379)),
+        ]);
+        assert_eq!(result, correct);
+
+        let value = Value::Array(vec![bulk("port"), bulk("6379")]);
+        let result = value.into_string_map();
+        assert!(result.is_ok(), "{:?}", result.err().unwrap());
+        assert_eq!(result.unwrap()["port"], bulk("6379"));
+
+        let value = Value::Map(vec![(Value::Integer(1), bulk("a"))]);
+        assert!(matches!(
+            value.into_string_map(),
+            Err(Error::InvalidValue(_))
+        ));
+        let value = Value::Map(vec![(Value::BulkString(vec![0xff]), bulk("a"))]);
+        assert!(matches!(
+            value.into_string_map(),
+            Err(Error::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn unwrap_singleton() {
+        let value = Value::Array(vec![bulk("a")]);
+        assert_eq!(value.unwrap_singleton(), bulk("a"));
+        let value = Value::Array(vec![bulk("a"), bulk("b")]);
+        assert_eq!(value.clone().unwrap_singleton(), value);
+        assert_eq!(Value::Integer(1).unwrap_singleton(), Value::Integer(1));
+    }
+
+    #[test]
+    fn canonical_bytes() {
+        let a = Value::Set(vec![bulk("b"), Value::Integer(1), bulk("a")]);
+        let b = Value::Set(vec![bulk("a"), bulk("b"), Value::Integer(1)]);
+        assert_ne!(a, b);
+        assert_eq!(a.canonical_bytes().unwrap(), b.canonical_bytes().unwrap());
+
+        let a = Value::Map(vec![
+            (bulk("x"), Value::Set(vec![bulk("2"), bulk("1")])),
+            (bulk("y"), Value::Null(NullKind::BulkString)),
+        ]);
+        let b = Value::Map(vec![
+            (bulk("y"), Value::Null(NullKind::Resp3)),
+            (bulk("x"), Value::Set(vec![bulk("1"), bulk("2")])),
+        ]);
+        assert_eq!(a.canonical_bytes().unwrap(), b.canonical_bytes().unwrap());
+
+        // Array order is meaningful.
+        let a = Value::Array(vec![bulk("a"), bulk("b")]);
+        let b = Value::Array(vec![bulk("b"), bulk("a")]);
+        assert_ne!(a.canonical_bytes().unwrap(), b.canonical_bytes().unwrap());
+
+        // Values the serializer rejects don't all map to the same key.
+        let a = Value::Set(vec![Value::String("a\r\nb".to_string())]);
+        match a.canonical_bytes() {
+            Err(Error::InvalidValue(_)) => {}
+            r => panic!("Expected invalid value error. Found: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn to_resp2_map() {
+        let value = Value::Map(vec![
+            (bulk("a"), Value::Double(1.5)),
+            (bulk("b"), Value::Null(NullKind::Resp3)),
+            (bulk("c"), Value::Map(vec![(bulk("d"), Value::Integer(1))])),
+        ]);
+        let correct = Value::Array(vec![
+            bulk("a"),
+            bulk("1.5"),
+            bulk("b"),
+            Value::Null(NullKind::BulkString),
+            bulk("c"),
+            Value::Array(vec![bulk("d"), Value::Integer(1)]),
+        ]);
+        assert_eq!(value.to_resp2(), correct);
+    }
+
+    #[test]
+    fn to_resp2_set() {
+        let value = Value::Set(vec![
+            bulk("a"),
+            Value::BigNumber("12345678901234567890".to_string()),
+            Value::BulkError(b"ERR bad\r\nthing".to_vec()),
+        ]);
+        let correct = Value::Array(vec![
+            bulk("a"),
+            bulk("12345678901234567890"),
+            Value::Error("ERR bad  thing".to_string()),
+        ]);
+        assert_eq!(value.to_resp2(), correct);
+        let value = Value::Array(vec![bulk("a"), Value::Null(NullKind::Array)]);
+        assert_eq!(value.to_resp2(), value);
+    }
+
+    #[test]
+    fn map_bulk_uppercase() {
+        let value = Value::Array(vec![
+            bulk("get"),
+            Value::Array(vec![bulk("key"), Value::String("ok".to_string())]),
+            Value::Integer(1),
+        ]);
+        let result = value.map_bulk(|b| b.to_ascii_uppercase());
+        let correct = Value::Array(vec![
+            bulk("GET"),
+            Value::Array(vec![bulk("KEY"), Value::String("ok".to_string())]),
+            Value::Integer(1),
+        ]);
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn fold_sum() {
+        let value = Value::Array(vec![
+            Value::Integer(1),
+            Value::Array(vec![Value::Integer(2), bulk("3")]),
+            Value::Map(vec![(
+                Value::Integer(4),
+                Value::Set(vec![Value::Integer(5)]),
+            )]),
+        ]);
+        let sum = value.fold(0, |acc, v| match v {
+            Value::Integer(i) => acc + i,
+            _ => acc,
+        });
+        assert_eq!(sum, 12);
+        assert_eq!(value.fold(0, |acc, _| acc + 1), 9);
+    }
+
+    #[test]
+    fn contains() {
+        let value = Value::Array(vec![bulk("a"), Value::Integer(1)]);
+        assert!(value.contains(&Value::Integer(1)));
+        assert!(!value.contains(&Value::Integer(2)));
+        let value = Value::Set(vec![bulk("a"), bulk("b")]);
+        assert!(value.contains(&bulk("b")));
+        assert!(!value.contains(&bulk("c")));
+        assert!(!bulk("a").contains(&bulk("a")));
+    }
+
+    #[test]
+    fn retain_map() {
+        let mut value = Value::Map(vec![
+            (bulk("maxmemory"), bulk("0")),
+            (bulk("_internal"), bulk("1")),
+            (bulk("port"), bulk("6379")),
+            (bulk("_debug"), bulk("yes")),
+        ]);
+        value.retain_map(|k, _| !k.try_str().is_ok_and(|k| k.starts_with('_')));
+        let correct = Value::Map(vec![
+            (bulk("maxmemory"), bulk("0")),
+            (bulk("port"), bulk("6379")),
+        ]);
+        assert_eq!(value, correct);
+
+        let mut value = Value::Array(vec![bulk("_a")]);
+        value.retain_map(|_, _| false);
+        assert_eq!(value, Value::Array(vec![bulk("_a")]));
+    }
+
+    #[test]
+    fn sort() {
+        let mut value = Value::Array(vec![
+            Value::Integer(3),
+            Value::Integer(-1),
+            Value::Integer(2),
+        ]);
+        value.sort();
+        let correct = Value::Array(vec![
+            Value::Integer(-1),
+            Value::Integer(2),
+            Value::Integer(3),
+        ]);
+        assert_eq!(value, correct);
+
+        let mut value = Value::Set(vec![bulk("b"), bulk("c"), bulk("a")]);
+        value.sort();
+        assert_eq!(value, Value::Set(vec![bulk("a"), bulk("b"), bulk("c")]));
+
+        let mixed = Value::Array(vec![Value::Integer(2), bulk("a"), Value::Integer(1)]);
+        let mut value = mixed.clone();
+        value.sort();
+        assert_eq!(value, mixed);
+    }
+
+    #[test]
+    fn retain_integers() {
+        let mut value = Value::Array(vec![
+            Value::Integer(1),
+            bulk("a"),
+            Value::Null(NullKind::BulkString),
+            Value::Integer(2),
+            Value::Array(vec![Value::Integer(3)]),
+        ]);
+        value.retain(|v| matches!(v, Value::Integer(_)));
+        assert_eq!(
+            value,
+            Value::Array(vec![Value::Integer(1), Value::Integer(2)])
+        );
+
+        let mut value = bulk("a");
+        value.retain(|_| false);
+        assert_eq!(value, bulk("a"));
+    }
+
+    #[test]
+    fn glob_star() {
+        assert!(bulk("user:1000").matches_glob("user:*"));
+        assert!(bulk("user:").matches_glob("user:*"));
+        assert!(bulk("a:b:c").matches_glob("*b*"));
+        assert!(!bulk("session:1").matches_glob("user:*"));
+    }
+
+    #[test]
+    fn glob_many_stars_long_key() {
+        // Used to backtrack exponentially, see CVE-2022-36021.
+        let key = bulk(&"a".repeat(10_000));
+        assert!(!key.matches_glob("*a*a*a*a*a*a*a*a*a*a*a*a*b"));
+        assert!(key.matches_glob("*a*a*a*a*a*a*a*a*a*a*a*a*"));
+        assert!(bulk("abcabd").matches_glob("*ab?"));
+        assert!(bulk("xaybzc").matches_glob("*a*b*c"));
+        assert!(!bulk("xaybz").matches_glob("*a*b*c"));
+        assert!(bulk("").matches_glob("**"));
+    }
+
+    #[test]
+    fn glob_question_mark() {
+        assert!(bulk("hello").matches_glob("h?llo"));
+        assert!(bulk("hallo").matches_glob("h?llo"));
+        assert!(!bulk("hllo").matches_glob("h?llo"));
+    }
+
+    #[test]
+    fn glob_character_class() {
+        assert!(bulk("hello").matches_glob("h[ae]llo"));
+        assert!(!bulk("hillo").matches_glob("h[ae]llo"));
+        assert!(bulk("hbllo").matches_glob("h[^e]llo"));
+        assert!(!bulk("hello").matches_glob("h[^e]llo"));
+        assert!(bulk("key7").matches_glob("key[0-9]"));
+        assert!(!bulk("keyx").matches_glob("key[0-9]"));
+    }
+
+    #[test]
+    fn glob_escape() {
+        assert!(bulk("what?").matches_glob("what\\?"));
+        assert!(!bulk("whats").matches_glob("what\\?"));
+    }
+
+    #[test]
+    fn glob_simple_string_and_other_variants() {
+        assert!(Value::String("OK".to_string()).matches_glob("O*"));
+        assert!(!Value::Integer(1).matches_glob("*"));
+        assert!(!Value::Null(NullKind::Resp3).matches_glob("*"));
+    }
+
+    #[test]
+    fn truncated_bulk_string() {
+        let value = Value::Array(vec![
+            Value::BulkString(vec![b'x'; 1000]),
+            bulk("short"),
+            Value::Integer(7),
+        ]);
+        let result = value.truncated(16);
+        let mut body = vec![b'x'; 16];
+        body.extend_from_slice(b"...");
+        let correct = Value::Array(vec![
+            Value::BulkString(body),
+            bulk("short"),
+            Value::Integer(7),
+        ]);
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn encode_lossy_binary_bulk_string() {
+        let value = Value::Array(vec![
+            Value::BulkString(vec![b'a', 0xff, 0xfe, b'b']),
+            Value::Integer(1),
+        ]);
+        let result = value.encode_lossy();
+        assert_eq!(result, "*2\r\n$4\r\na\\xff\\xfeb\r\n:1\r\n");
+    }
+
+    #[test]
+    fn encode_lossy_unserializable() {
+        let value = Value::Array(vec![
+            Value::String("a\r\nb".to_string()),
+            Value::BigNumber("12x".to_string()),
+            Value::Error("ERR\n".to_string()),
+        ]);
+        assert!(serializer::to_bytes(&value).is_err());
+        let result = value.encode_lossy();
+        assert_eq!(result, "*3\r\n+a\\r\\nb\r\n(12x\r\n-ERR\\n\r\n");
+        // Whatever the serializer accepts comes out the same.
+        let value = Value::Map(vec![(bulk("a\r\n"), Value::Null(NullKind::Array))]);
+        let bytes = serializer::to_bytes(&value).unwrap();
+        assert_eq!(value.encode_lossy().as_bytes(), &bytes[..]);
+    }
+
+    #[test]
+    fn as_error() {
+        let value = Value::Error("ERR unknown command".to_string());
+        assert_eq!(value.as_error(), Some("ERR unknown command"));
+        let value = Value::BulkError(b"SYNTAX invalid syntax".to_vec());
+        assert_eq!(value.as_error(), Some("SYNTAX invalid syntax"));
+        assert_eq!(bulk("ERR not an error").as_error(), None);
+    }
+
+    #[test]
+    fn predicates() {
+        let values = [
+            Value::Error("ERR".to_string()),
+            Value::Null(NullKind::Resp3),
+            Value::Array(vec![bulk("a")]),
+            Value::Integer(1),
+            Value::String("OK".to_string()),
+            bulk("a"),
+        ];
+        let predicates: [fn(&Value) -> bool; 6] = [
+            Value::is_error,
+            Value::is_nil,
+            Value::is_array,
+            Value::is_integer,
+            Value::is_string,
+            Value::is_bulk,
+        ];
+        for (i, value) in values.iter().enumerate() {
+            for (j, predicate) in predicates.iter().enumerate() {
+                assert_eq!(predicate(value), i == j, "{:?} with predicate {}", value, j);
+            }
+        }
+        assert!(Value::BulkError(b"ERR".to_vec()).is_error());
+        assert!(Value::Null(NullKind::Array).is_nil());
+        assert!(!Value::Set(vec![]).is_array());
+    }
+
+    #[test]
+    fn replace_nulls() {
+        let value = Value::Array(vec![
+            bulk("a"),
+            Value::Null(NullKind::BulkString),
+            Value::Array(vec![Value::Null(NullKind::Resp3), Value::Integer(1)]),
+        ]);
+        let correct = Value::Array(vec![
+            bulk("a"),
+            bulk(""),
+            Value::Array(vec![bulk(""), Value::Integer(1)]),
+        ]);
+        assert_eq!(value.replace_nulls(bulk("")), correct);
+        assert_eq!(
+            Value::Null(NullKind::Array).replace_nulls(Value::Array(vec![])),
+            Value::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn prune_depth() {
+        let mut value = Value::Integer(1);
+        for _ in 0..4 {
+            value = Value::Array(vec![value, bulk("a")]);
+        }
+        assert_eq!(value.depth(), 5);
+        let result = value.prune_depth(2);
+        let correct = Value::Array(vec![Value::String("...".to_string()), bulk("a")]);
+        assert_eq!(result, correct);
+        assert_eq!(result.depth(), 2);
+        assert_eq!(value.prune_depth(5), value);
+        assert_eq!(
+            Value::Array(vec![Value::Array(vec![])]).prune_depth(2),
+            Value::Array(vec![Value::Array(vec![])])
+        );
+
+        let value = Value::WithAttributes {
+            attributes: Box::new(Value::Map(vec![(bulk("ttl"), Value::Integer(3))])),
+            value: Box::new(Value::Array(vec![bulk("a")])),
+        };
+        assert_eq!(value.prune_depth(usize::MAX), value);
+    }
+
+    #[test]
+    fn command_name() {
+        let value = Value::Array(vec![bulk("echo"), bulk("hey")]);
+        assert!(value.is_command());
+        assert_eq!(value.command_name(), Some("ECHO".to_string()));
+
+        let value = Value::Array(vec![bulk("ECHO"), Value::Integer(1)]);
+        assert!(!value.is_command());
+        assert_eq!(value.command_name(), None);
+        assert!(!Value::Array(vec![]).is_command());
+        assert!(!bulk("ECHO").is_command());
+        let value = Value::Array(vec![Value::BulkString(vec![0xff])]);
+        assert_eq!(value.command_name(), None);
+    }
+
+    #[test]
+    fn approx_memory_size() {
+        assert_eq!(Value::Integer(1).approx_memory_size(), 0);
+        assert_eq!(bulk("hey").approx_memory_size(), 3);
+        let value = Value::Array(vec![bulk("ECHO"), bulk("hey")]);
+        assert_eq!(
+            value.approx_memory_size(),
+            2 * std::mem::size_of::<Value>() + 7
+        );
+        let value = Value::Map(vec![(bulk("a"), Value::Array(vec![Value::Integer(1)]))]);
+        assert_eq!(
+            value.approx_memory_size(),
+            3 * std::mem::size_of::<Value>() + 1
+        );
+    }
+
+    #[test]
+    fn strip_attributes() {
+        let attributes = |value: Value| Value::WithAttributes {
+            attributes: Box::new(Value::Map(vec![(bulk("ttl"), Value::Integer(3))])),
+            value: Box::new(value),
+        };
+        let value = attributes(Value::Array(vec![
+            attributes(attributes(bulk("a"))),
+            Value::Integer(1),
+        ]));
+        let correct = Value::Array(vec![bulk("a"), Value::Integer(1)]);
+        assert_eq!(value.strip_attributes(), correct);
+    }
+
+    #[test]
+    fn as_duration() {
+        for data in [":-1\r\n", ":-2\r\n"] {
+            let value: Value = data.parse().unwrap();
+            assert_eq!(value.as_duration_secs(), None);
+            assert_eq!(value.as_duration_millis(), None);
+        }
+        let value: Value = ":120\r\n".parse().unwrap();
+        assert_eq!(value.as_duration_secs(), Some(Duration::from_secs(120)));
+        assert_eq!(value.as_duration_millis(), Some(Duration::from_millis(120)));
+        assert_eq!(bulk("120").as_duration_secs(), None);
+    }
+
+    #[test]
+    fn command_matches() {
+        let value = Value::Array(vec![bulk("ECHO"), bulk("hey")]);
+        assert!(value.command_matches("echo"));
+        assert!(value.command_matches("ECHO"));
+        assert!(!value.command_matches("ech"));
+        assert!(!value.command_matches("get"));
+        assert!(!bulk("ECHO").command_matches("echo"));
+    }
+
+    #[test]
+    fn join_bulk() {
+        let value = Value::Array(vec![bulk("a"), bulk("b"), bulk("c")]);
+        assert_eq!(value.join_bulk(b","), Some(b"a,b,c".to_vec()));
+        assert_eq!(Value::Array(vec![]).join_bulk(b","), Some(vec![]));
+        let value = Value::Array(vec![bulk("a"), Value::Integer(1)]);
+        assert_eq!(value.join_bulk(b","), None);
+        assert_eq!(bulk("a").join_bulk(b","), None);
+    }
+
+    #[test]
+    fn depth() {
+        assert_eq!(Value::Integer(1).depth(), 1);
+        assert_eq!(Value::Array(vec![]).depth(), 1);
+        assert_eq!(Value::Array(vec![bulk("a"), Value::Integer(1)]).depth(), 2);
+        let nested = Value::Array(vec![Value::Integer(1), Value::Array(vec![bulk("a")])]);
+        assert_eq!(nested.depth(), 3);
+    }
+
+    #[test]
+    fn as_f64() {
+        assert_eq!(Value::Integer(5).as_f64(), Some(5.0));
+        assert_eq!(Value::Double(2.5).as_f64(), Some(2.5));
+        assert_eq!(
+            Value::BigNumber("-12345".to_string()).as_f64(),
+            Some(-12345.0)
+        );
+        assert_eq!(Value::BigNumber("9".repeat(400)).as_f64(), None);
+        assert_eq!(bulk("5").as_f64(), None);
+    }
+}
@@ -0,0 +1,236 @@
+use resp_parser::deserializer::{from_bytes, Deserializer, Error, NullKind, Value};
+use resp_parser::serializer::{to_bytes, to_bytes_with_version, ProtocolVersion};
+
+fn bulk(data: &str) -> Value {
+    Value::BulkString(Vec::from(data.as_bytes()))
+}
+
+fn assert_roundtrip(value: Value) {
+    let bytes = to_bytes(&value);
+    assert!(bytes.is_ok(), "{:?}", bytes.err().unwrap());
+    let bytes = bytes.unwrap();
+    let result = from_bytes(&bytes);
+    assert!(
+        result.is_ok(),
+        "{:?} for {:?}",
+        result.err().unwrap(),
+        String::from_utf8_lossy(&bytes)
+    );
+    assert_eq!(result.unwrap(), value);
+}
+
+#[test]
+fn roundtrip_simple_string() {
+    assert_roundtrip(Value::String("OK".to_string()));
+    assert_roundtrip(Value::String(String::new()));
+}
+
+#[test]
+fn roundtrip_error() {
+    assert_roundtrip(Value::Error("ERR unknown command 'foo'".to_string()));
+}
+
+#[test]
+fn roundtrip_bulk_error() {
+    assert_roundtrip(Value::BulkError(b"SYNTAX invalid\r\nsyntax".to_vec()));
+}
+
+#[test]
+fn roundtrip_verbatim() {
+    assert_roundtrip(Value::Verbatim {
+        format: "mkd".to_string(),
+        data: b"# Title\r\n\r\nText".to_vec(),
+    });
+    assert_roundtrip(Value::Verbatim {
+        format: "txt".to_string(),
+        data: vec![],
+    });
+
+    // Whatever parses serializes back the same, and a format the serializer
+    // rejects doesn't parse.
+    let data = b"=7\r\nmkd:abc\r\n";
+    assert_eq!(to_bytes(&from_bytes(data).unwrap()).unwrap(), data);
+    let data = b"=7\r\ntx1:abc\r\n";
+    assert!(from_bytes(data).is_err());
+    let value = Value::Verbatim {
+        format: "tx1".to_string(),
+        data: b"abc".to_vec(),
+    };
+    assert!(to_bytes(&value).is_err());
+}
+
+#[test]
+fn roundtrip_integer() {
+    assert_roundtrip(Value::Integer(0));
+    assert_roundtrip(Value::Integer(i64::MAX));
+    assert_roundtrip(Value::Integer(i64::MIN));
+}
+
+#[test]
+fn roundtrip_double() {
+    assert_roundtrip(Value::Double(2.5));
+    assert_roundtrip(Value::Double(-0.5e-300));
+    assert_roundtrip(Value::Double(1e300));
+    assert_roundtrip(Value::Double(f64::INFINITY));
+    assert_roundtrip(Value::Double(f64::NEG_INFINITY));
+}
+
+#[test]
+fn roundtrip_raw_double() {
+    for data in [&b",3.140000\r\n"[..], b",1e3\r\n", b",-inf\r\n"] {
+        let mut d = Deserializer::new(data).with_raw_doubles(true);
+        let value = d.parse();
+        assert!(value.is_ok(), "{:?}", value.err().unwrap());
+        let value = value.unwrap();
+        assert!(
+            matches!(value, Value::RawDouble { .. }),
+            "Expected raw double. Found: {:?}",
+            value
+        );
+        let bytes = to_bytes(&value);
+        assert!(bytes.is_ok(), "{:?}", bytes.err().unwrap());
+        assert_eq!(bytes.unwrap(), data);
+    }
+
+    let value = Value::RawDouble {
+        value: 1.0,
+        raw: "1\r\n".to_string(),
+    };
+    let result = to_bytes(&value);
+    assert!(result.is_err(), "Found: {:?}", result.unwrap());
+}
+
+#[test]
+fn roundtrip_big_number() {
+    assert_roundtrip(Value::BigNumber(
+        "3492890328409238509324850943850943825024385".to_string(),
+    ));
+    assert_roundtrip(Value::BigNumber("-1".to_string()));
+}
+
+#[test]
+fn roundtrip_bulk_string() {
+    assert_roundtrip(bulk("hey"));
+    assert_roundtrip(bulk(""));
+    assert_roundtrip(Value::BulkString(b"\r\n\0\xff binary".to_vec()));
+}
+
+#[test]
+fn roundtrip_null() {
+    assert_roundtrip(Value::Null(NullKind::BulkString));
+    assert_roundtrip(Value::Null(NullKind::Array));
+    assert_roundtrip(Value::Null(NullKind::Resp3));
+}
+
+#[test]
+fn roundtrip_null_encodings() {
+    for data in [&b"$-1\r\n"[..], b"*-1\r\n", b"_\r\n"] {
+        let value = from_bytes(data);
+        assert!(value.is_ok(), "{:?}", value.err().unwrap());
+        let bytes = to_bytes(&value.unwrap());
+        assert!(bytes.is_ok(), "{:?}", bytes.err().unwrap());
+        assert_eq!(bytes.unwrap(), data);
+    }
+}
+
+#[test]
+fn roundtrip_null_per_version() {
+    let value = Value::Array(vec![
+        Value::Null(NullKind::BulkString),
+        Value::Null(NullKind::Array),
+        Value::Null(NullKind::Resp3),
+    ]);
+    for (version, correct) in [
+        (ProtocolVersion::Resp2, &b"*3\r\n$-1\r\n*-1\r\n$-1\r\n"[..]),
+        (ProtocolVersion::Resp3, &b"*3\r\n_\r\n_\r\n_\r\n"[..]),
+    ] {
+        let bytes = to_bytes_with_version(&value, version);
+        assert!(bytes.is_ok(), "{:?}", bytes.err().unwrap());
+        assert_eq!(bytes.unwrap(), correct);
+    }
+}
+
+#[test]
+fn roundtrip_array() {
+    assert_roundtrip(Value::Array(vec![]));
+    assert_roundtrip(Value::Array(vec![bulk("ECHO"), bulk("hey")]));
+}
+
+#[test]
+fn roundtrip_nested() {
+    assert_roundtrip(Value::Array(vec![
+        Value::Array(vec![
+            Value::Integer(1),
+            Value::Null(NullKind::Array),
+            Value::Array(vec![]),
+        ]),
+        Value::String("OK".to_string()),
+        Value::Error("ERR".to_string()),
+        Value::Map(vec![(bulk("key"), Value::Array(vec![bulk("value")]))]),
+    ]));
+}
+
+#[test]
+fn roundtrip_map() {
+    assert_roundtrip(Value::Map(vec![]));
+    assert_roundtrip(Value::Map(vec![
+        (bulk("first"), Value::Integer(1)),
+        (Value::Integer(2), Value::Null(NullKind::Resp3)),
+    ]));
+}
+
+#[test]
+fn roundtrip_set() {
+    assert_roundtrip(Value::Set(vec![]));
+    assert_roundtrip(Value::Set(vec![bulk("a"), Value::Integer(1)]));
+}
+
+#[test]
+fn roundtrip_attributes() {
+    assert_roundtrip(Value::WithAttributes {
+        attributes: Box::new(Value::Map(vec![(
+            Value::String("key-popularity".to_string()),
+            Value::Integer(7),
+        )])),
+        value: Box::new(Value::Array(vec![Value::Integer(1)])),
+    });
+}
+
+#[test]
+fn roundtrip_unknown() {
+    let value = Value::Unknown {
+        prefix: b'@',
+        raw_line: b"made up".to_vec(),
+    };
+    let bytes = to_bytes(&value).unwrap();
+    let mut d = Deserializer::new(bytes.as_slice()).with_unknown_types(true);
+    assert_eq!(d.parse().unwrap(), value);
+}
+
+#[test]
+fn line_terminator_in_simple_string_is_rejected() {
+    // These can't survive a round trip, so they mustn't be written either.
+    let result = to_bytes(&Value::String("a\r\nb".to_string()));
+    assert!(result.is_err(), "Found: {:?}", result.unwrap());
+    let result = to_bytes(&Value::Error("a\nb".to_string()));
+    assert!(result.is_err(), "Found: {:?}", result.unwrap());
+}
+
+// `Value` and `Error` are non exhaustive, downstream matches need a wildcard.
+#[test]
+fn match_with_wildcard() {
+    let describe = |value: &Value| match value {
+        Value::String(s) => format!("status {}", s),
+        Value::Integer(i) => format!("integer {}", i),
+        _ => "something else".to_string(),
+    };
+    assert_eq!(describe(&Value::Integer(3)), "integer 3");
+    assert_eq!(describe(&Value::Null(NullKind::Resp3)), "something else");
+
+    let error = from_bytes(b"?\r\n").unwrap_err();
+    let described = match error {
+        Error::InvalidValue(_) => "invalid",
+        _ => "other",
+    };
+    assert_eq!(described, "invalid");
+}
@@ -0,0 +1,44 @@
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use resp_parser::deserializer::{Deserializer, Value};
+
+// Binds a loopback port, so it's left out of the default run.
+#[test]
+#[ignore]
+fn parse_pipelined_replies_from_socket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        socket.write_all(b"+OK\r\n*2\r\n$3\r\nfoo\r\n$").unwrap();
+        socket.flush().unwrap();
+        // The rest of the second reply comes later, mid-frame.
+        thread::sleep(Duration::from_millis(100));
+        socket.write_all(b"3\r\nbar\r\n:42\r\n").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        socket.write_all(b"$5\r\nhello\r\n").unwrap();
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    let mut d = Deserializer::new(stream);
+    assert_eq!(
+        d.next_value().unwrap(),
+        Some(Value::String("OK".to_string()))
+    );
+    let correct = Value::Array(vec![
+        Value::BulkString(b"foo".to_vec()),
+        Value::BulkString(b"bar".to_vec()),
+    ]);
+    assert_eq!(d.next_value().unwrap(), Some(correct));
+    assert_eq!(d.next_value().unwrap(), Some(Value::Integer(42)));
+    assert_eq!(
+        d.next_value().unwrap(),
+        Some(Value::BulkString(b"hello".to_vec()))
+    );
+    server.join().unwrap();
+    // The server closed the connection between two frames.
+    assert_eq!(d.next_value().unwrap(), None);
+}